@@ -0,0 +1,111 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::Path;
+use tokio::fs;
+
+use eyre::{Result, bail};
+
+use crate::{
+    actions::{prepare, prepare_cached},
+    config, dirs,
+    models::Spec,
+    util,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct BinPathOptions {
+    pub spec: Option<Spec>,
+    pub fetch: bool,
+}
+
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+#[cfg(unix)]
+async fn write_script(path: &Path, contents: &str) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    if fs::read_to_string(path)
+        .await
+        .is_ok_and(|existing| existing == contents)
+    {
+        return Ok(());
+    }
+
+    util::write_atomic(path, contents).await?;
+    fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).await?;
+
+    Ok(())
+}
+
+#[cfg(windows)]
+#[expect(clippy::unused_async)]
+async fn write_script(_path: &Path, _contents: &str) -> Result<()> {
+    bail!("`bin-path` is not supported on Windows yet");
+}
+
+pub async fn bin_path(options: &BinPathOptions) -> Result<()> {
+    let spec = match &options.spec {
+        Some(spec) => spec,
+        None => &match Spec::parse(true).await? {
+            Some(spec) => spec,
+            None => bail!("no `packageManager` or `devEngines.packageManager` configured!"),
+        },
+    };
+
+    let (cache_path, bins) = match prepare_cached(spec).await? {
+        Some(outcome) => outcome,
+        None if options.fetch => prepare(spec).await?,
+        None => bail!(
+            "{spec:#} is not cached; pass `--fetch` to fetch it, or run `moldau prefetch {spec}`"
+        ),
+    };
+
+    let standalone = config::get().use_standalone(spec.name);
+
+    let dir_name = format!(
+        "{}{}-{}",
+        spec.name,
+        if standalone { "-standalone" } else { "" },
+        cache_path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default()
+    );
+    let dir = dirs::data().join("bins").join(dir_name);
+
+    fs::create_dir_all(&dir).await?;
+
+    // Package manager bins are scripts that have to be run with Node.js, like
+    // `exec` does, rather than executables that could be linked to directly
+    let node = config::get().node.as_deref().unwrap_or(Path::new("node"));
+
+    for (name, script) in &bins {
+        let script_path = std::path::absolute(cache_path.join(script))?;
+
+        if !util::is_within(&cache_path, &script_path)? {
+            bail!("bin {script:?} of {spec:#} is outside of the package");
+        }
+
+        let contents = if standalone {
+            // The standalone executable has no separate `pnpx` entry point
+            let dlx = if name == "pnpx" { " dlx" } else { "" };
+            format!("#!/bin/sh\nexec {}{dlx} \"$@\"\n", quote(&script_path))
+        } else {
+            format!(
+                "#!/bin/sh\nexec {} {} \"$@\"\n",
+                quote(node),
+                quote(&script_path)
+            )
+        };
+
+        write_script(&dir.join(name), &contents).await?;
+    }
+
+    println!("{}", dir.display());
+
+    Ok(())
+}
@@ -2,45 +2,117 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::collections::BTreeSet;
 use tokio::fs;
 
+use clap::ValueEnum;
 use eyre::Result;
 use log::{debug, info};
 use owo_colors::{OwoColorize as _, colors::Blue};
 
-use crate::{dirs, models::SpecName, util::LogDisplay as _};
+use crate::{
+    cache,
+    models::{Spec, SpecName, SpecVersion},
+    util::LogDisplay as _,
+};
 
-pub async fn clean(all: bool) -> Result<()> {
-    let all_versions_path = dirs::cache().join("versions");
+/// Parts of the cache that `clean` removes versions from.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CleanScope {
+    /// Both kinds of cached versions
+    #[default]
+    All,
+    /// Versions installed from npm packages
+    Package,
+    /// Standalone executables, like pnpm's `@pnpm/<os>-<arch>`
+    Standalone,
+}
 
-    for name in SpecName::VARIANTS {
-        let mut cached_versions: BTreeSet<semver::Version> = BTreeSet::new();
-        let versions_path = all_versions_path.join(name.to_string());
+#[derive(Clone, Debug)]
+pub struct CleanOptions {
+    pub name: Option<SpecName>,
+    pub scope: CleanScope,
+    pub all: bool,
+    pub keep: usize,
+}
 
-        if let Ok(mut read_dir) = fs::read_dir(&versions_path).await {
-            while let Some(entry) = read_dir.next_entry().await? {
-                if let Ok(version) = semver::Version::parse(&entry.file_name().to_string_lossy()) {
-                    cached_versions.insert(version);
-                }
-            }
+impl Default for CleanOptions {
+    fn default() -> Self {
+        Self {
+            name: None,
+            scope: CleanScope::default(),
+            all: false,
+            keep: 1,
         }
+    }
+}
+
+pub async fn clean(options: &CleanOptions) -> Result<()> {
+    let keep = if options.all { 0 } else { options.keep };
 
-        if !all {
-            cached_versions.pop_last();
+    // The project's own pin would have to be downloaded again right away
+    let pinned = match Spec::parse(true).await.ok().flatten() {
+        Some(Spec {
+            name,
+            version: SpecVersion::Exact(mut version),
+        }) => {
+            version.build = semver::BuildMetadata::EMPTY;
+            Some((name, version))
         }
+        _ => None,
+    };
+
+    let names = match &options.name {
+        Some(name) => std::slice::from_ref(name),
+        None => SpecName::VARIANTS,
+    };
+
+    for name in names {
+        let mut removed = 0_usize;
+
+        let versions_paths = match options.scope {
+            CleanScope::All => vec![
+                cache::versions_dir(*name),
+                cache::standalone_versions_dir(*name),
+            ],
+            CleanScope::Package => vec![cache::versions_dir(*name)],
+            CleanScope::Standalone => vec![cache::standalone_versions_dir(*name)],
+        };
+
+        for versions_path in versions_paths {
+            let mut cached_versions = cache::cached_versions_in(&versions_path).await?;
+
+            for _ in 0..keep {
+                cached_versions.pop_last();
+            }
+
+            if let Some((pinned_name, pinned_version)) = &pinned
+                && pinned_name == name
+            {
+                let before = cached_versions.len();
+                cached_versions.retain(|version| version.cmp_precedence(pinned_version).is_ne());
+
+                if cached_versions.len() < before {
+                    info!(
+                        "kept {} as it is pinned by the current project",
+                        format!("{name}@{pinned_version}").log_display::<Blue>()
+                    );
+                }
+            }
+
+            for version in &cached_versions {
+                let path = versions_path.join(version.to_string());
+                fs::remove_dir_all(&path).await?;
+                debug!("removed {version} -> {}", path.display());
+            }
 
-        for version in &cached_versions {
-            let path = versions_path.join(version.to_string());
-            fs::remove_dir_all(&path).await?;
-            debug!("removed {version} -> {}", path.display());
+            removed += cached_versions.len();
         }
 
         info!(
             "removed {} versions of {}{}",
-            cached_versions.len().green(),
+            removed.green(),
             name.log_display::<Blue>(),
-            if all {
+            if options.all {
                 " (including latest)".dimmed().to_string()
             } else {
                 String::new()
@@ -0,0 +1,228 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    io::{self, Write as _},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use clap_complete::Shell;
+use eyre::{Result, bail};
+use log::warn;
+
+use crate::{
+    cache,
+    models::{NpmPackage, Spec, SpecBin, SpecName, SpecVersion},
+};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionKind {
+    /// Package manager names, cached versions, and dist tags
+    Spec,
+    /// Package manager names
+    SpecName,
+    /// Cached `name@version` specifications
+    CachedVersion,
+    /// `name@tag` specifications from the registry's dist tags
+    DistTag,
+    /// Package manager binaries
+    Bin,
+}
+
+/// Completing should never keep the shell waiting for long, so dist tags are
+/// skipped if the registry is slow.
+const DIST_TAG_TIMEOUT: Duration = Duration::from_secs(2);
+
+const SPEC_DESCRIPTION: &str = "Specification for the package manager";
+const COMMAND_DESCRIPTION: &str =
+    "Package manager binary to execute, followed by the arguments to pass to it";
+
+const ZSH_HELPERS: &str = r#"
+_moldau_dynamic() {
+    local -a candidates
+    candidates=(${(f)"$(moldau __complete "$1" -- "$PREFIX" 2>/dev/null)"})
+    compadd -a candidates
+}
+
+_moldau_spec() {
+    _moldau_dynamic spec
+}
+
+_moldau_exec_command() {
+    if (( CURRENT == 1 )); then
+        _moldau_dynamic bin
+    else
+        _default
+    fi
+}
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+complete -c moldau -n "__fish_moldau_using_subcommand use prefetch info extract" -f -a "(moldau __complete spec -- (commandline -ct) 2>/dev/null)"
+complete -c moldau -n "__fish_moldau_using_subcommand exec" -l spec -r -f -a "(moldau __complete spec -- (commandline -ct) 2>/dev/null)"
+complete -c moldau -n "__fish_moldau_using_subcommand exec; and test (count (commandline -opc)) -le 2" -f -a "(moldau __complete bin -- (commandline -ct) 2>/dev/null)"
+"#;
+
+fn generate_static(cmd: &mut clap::Command, shell: Shell) -> String {
+    let mut buf = Vec::new();
+    clap_complete::generate(shell, cmd, "moldau", &mut buf);
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+/// Replace `from` in a generated script, failing if it is not there so that
+/// changes to the output of `clap_complete` don't silently break completions.
+fn replace_generated(script: &str, from: &str, to: &str) -> Result<String> {
+    if !script.contains(from) {
+        bail!("could not find `{from}` in the generated completions");
+    }
+
+    Ok(script.replace(from, to))
+}
+
+fn generate_zsh(cmd: &mut clap::Command) -> Result<String> {
+    let script = generate_static(cmd, Shell::Zsh);
+
+    let script = replace_generated(
+        &script,
+        &format!("{SPEC_DESCRIPTION}:_default'"),
+        &format!("{SPEC_DESCRIPTION}:_moldau_spec'"),
+    )?;
+    let script = replace_generated(
+        &script,
+        &format!("[{SPEC_DESCRIPTION}]:SPEC:_default'"),
+        &format!("[{SPEC_DESCRIPTION}]:SPEC:_moldau_spec'"),
+    )?;
+    let script = replace_generated(
+        &script,
+        &format!("{COMMAND_DESCRIPTION}:_default'"),
+        &format!("{COMMAND_DESCRIPTION}:_moldau_exec_command'"),
+    )?;
+
+    // The helpers have to be defined before `_moldau` is first called at the
+    // end of the script, so they go right after the `#compdef` line
+    let Some((compdef, rest)) = script.split_once('\n') else {
+        bail!("could not find the `#compdef` line in the generated completions");
+    };
+
+    Ok(format!("{compdef}\n{ZSH_HELPERS}{rest}"))
+}
+
+pub fn completions(cmd: &mut clap::Command, shell: Shell, dynamic: bool) -> Result<()> {
+    let script = match shell {
+        Shell::Zsh if dynamic => generate_zsh(cmd)?,
+        Shell::Fish if dynamic => generate_static(cmd, shell) + FISH_DYNAMIC,
+        _ => {
+            if dynamic {
+                warn!("dynamic completions are not supported for {shell}, generating static ones");
+            }
+
+            generate_static(cmd, shell)
+        }
+    };
+
+    io::stdout().write_all(script.as_bytes())?;
+
+    Ok(())
+}
+
+async fn cached_version_candidates(names: &[SpecName]) -> Result<Vec<String>> {
+    let mut candidates = Vec::new();
+
+    for name in names {
+        for version in cache::cached_versions(*name).await?.iter().rev() {
+            candidates.push(format!("{name}@{version}"));
+        }
+    }
+
+    Ok(candidates)
+}
+
+async fn dist_tag_candidates(name: SpecName) -> Result<Vec<String>> {
+    let spec = Spec {
+        name,
+        version: SpecVersion::default(),
+    };
+
+    let package = tokio::time::timeout(DIST_TAG_TIMEOUT, NpmPackage::fetch(&spec)).await??;
+
+    let mut tags = package.dist_tags.into_keys().collect::<Vec<_>>();
+    tags.sort_unstable();
+
+    Ok(tags
+        .into_iter()
+        .map(|tag| format!("{name}@{tag}"))
+        .collect())
+}
+
+async fn candidates(kind: CompletionKind, partial: &str) -> Result<Vec<String>> {
+    // Only complete versions of the package manager that is being typed, if any
+    let typed_name = partial
+        .split_once('@')
+        .and_then(|(name, _)| name.parse::<SpecName>().ok());
+
+    let names = typed_name.as_slice();
+    let names = if names.is_empty() {
+        SpecName::VARIANTS
+    } else {
+        names
+    };
+
+    Ok(match kind {
+        CompletionKind::Spec => {
+            let mut candidates = SpecName::VARIANTS
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+
+            candidates.extend(cached_version_candidates(names).await?);
+
+            if let Some(name) = typed_name {
+                candidates.extend(dist_tag_candidates(name).await.unwrap_or_default());
+            }
+
+            candidates
+        }
+
+        CompletionKind::SpecName => SpecName::VARIANTS.iter().map(ToString::to_string).collect(),
+        CompletionKind::CachedVersion => cached_version_candidates(names).await?,
+
+        CompletionKind::DistTag => match typed_name {
+            Some(name) => dist_tag_candidates(name).await?,
+            None => Vec::new(),
+        },
+
+        CompletionKind::Bin => SpecBin::VARIANTS.iter().map(ToString::to_string).collect(),
+    })
+}
+
+/// Print completion candidates for the generated dynamic completion scripts.
+/// Errors are swallowed so that completing never breaks the shell.
+pub async fn complete(kind: CompletionKind, partial: &str) {
+    let Ok(candidates) = candidates(kind, partial).await else {
+        return;
+    };
+
+    let mut stdout = io::stdout().lock();
+
+    for candidate in candidates.iter().filter(|c| c.starts_with(partial)) {
+        if writeln!(stdout, "{candidate}").is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_replacements_are_errors() {
+        assert_eq!(
+            replace_generated("a:_default'", ":_default'", ":_moldau_spec'").unwrap(),
+            "a:_moldau_spec'"
+        );
+        assert!(replace_generated("a:_files'", ":_default'", ":_moldau_spec'").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{env, path::Path};
+use tokio::fs;
+
+use eyre::Result;
+use log::{info, warn};
+use owo_colors::OwoColorize as _;
+
+use crate::{
+    actions::{self, ShimsOptions},
+    cache, dirs,
+    models::{Spec, SpecBin, SpecName, SpecVersion},
+    util,
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DoctorOptions {
+    pub fix: bool,
+    pub yes: bool,
+}
+
+fn confirm_fix(prompt: &str, options: DoctorOptions) -> Result<bool> {
+    if options.yes {
+        return Ok(true);
+    }
+
+    if !util::is_interactive() {
+        warn!("skipping fix; pass `--yes` to apply it without confirmation");
+        return Ok(false);
+    }
+
+    util::confirm(prompt)
+}
+
+async fn incomplete_versions_in(versions_dir: &Path) -> Result<Vec<semver::Version>> {
+    let mut incomplete = Vec::new();
+
+    for version in cache::cached_versions_in(versions_dir).await? {
+        if !cache::is_complete(&versions_dir.join(version.to_string())) {
+            incomplete.push(version);
+        }
+    }
+
+    Ok(incomplete)
+}
+
+/// Incomplete cached versions, together with whether they are standalone
+/// executables rather than npm packages.
+async fn incomplete_versions() -> Result<Vec<(Spec, bool)>> {
+    let mut incomplete = Vec::new();
+
+    for name in SpecName::VARIANTS {
+        for (versions_dir, standalone) in [
+            (cache::versions_dir(*name), false),
+            (cache::standalone_versions_dir(*name), true),
+        ] {
+            for version in incomplete_versions_in(&versions_dir).await? {
+                incomplete.push((
+                    Spec {
+                        name: *name,
+                        version: SpecVersion::Exact(version),
+                    },
+                    standalone,
+                ));
+            }
+        }
+    }
+
+    Ok(incomplete)
+}
+
+pub async fn doctor(options: &DoctorOptions) -> Result<()> {
+    let mut issues = 0_usize;
+
+    let cache_dir = dirs::cache();
+
+    if cache_dir.is_dir() {
+        info!("cache directory {} exists", cache_dir.display());
+    } else {
+        issues += 1;
+        warn!("cache directory {} does not exist", cache_dir.display());
+
+        if options.fix {
+            fs::create_dir_all(&cache_dir).await?;
+            info!("{} created {}", "fixed".green(), cache_dir.display());
+        }
+    }
+
+    let shims_dir = dirs::shims();
+    let missing_shims = SpecBin::VARIANTS
+        .iter()
+        .filter(|bin| shims_dir.join(bin.to_string()).symlink_metadata().is_err())
+        .collect::<Vec<_>>();
+
+    if missing_shims.is_empty() {
+        info!("shims are installed in {}", shims_dir.display());
+    } else {
+        issues += 1;
+        warn!(
+            "{} shims are missing from {}",
+            missing_shims.len().yellow(),
+            shims_dir.display()
+        );
+
+        if options.fix {
+            actions::shims(&shims_dir, &ShimsOptions::default()).await?;
+            info!("{} installed shims", "fixed".green());
+        }
+    }
+
+    if !env::var_os("PATH").is_some_and(|s| env::split_paths(&s).any(|p| p == shims_dir)) {
+        issues += 1;
+        warn!(
+            "{} is not in PATH; see `moldau env` for adding it",
+            shims_dir.display()
+        );
+    }
+
+    let stale = cache::stale_temp_dirs().await?;
+
+    if !stale.is_empty() {
+        issues += 1;
+        warn!(
+            "found {} stale temporary directories from interrupted fetches",
+            stale.len().yellow()
+        );
+
+        if options.fix {
+            let removed = cache::remove_stale_temp_dirs().await?;
+            info!(
+                "{} removed {} stale temporary directories",
+                "fixed".green(),
+                removed.green()
+            );
+        }
+    }
+
+    for (spec, standalone) in incomplete_versions().await? {
+        issues += 1;
+
+        let kind = if standalone {
+            "standalone version"
+        } else {
+            "version"
+        };
+        warn!("cached {kind} {spec:#} is incomplete");
+
+        if options.fix && confirm_fix(&format!("remove and re-fetch {spec:#}?"), *options)? {
+            if standalone {
+                actions::fetch_standalone(&spec, None).await?;
+            } else {
+                actions::fetch_package(&spec, None).await?;
+            }
+
+            info!("{} re-fetched {kind} {spec:#}", "fixed".green());
+        }
+    }
+
+    if issues == 0 {
+        info!("no issues found");
+    } else if !options.fix {
+        info!(
+            "found {} issues; run `moldau doctor --fix` to attempt repairs",
+            issues.yellow()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs as std_fs;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn versions_without_package_json_are_incomplete() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+
+        std_fs::create_dir(tmp.path().join("9.4.0")).unwrap();
+        std_fs::write(tmp.path().join("9.4.0").join("package.json"), "{}").unwrap();
+        std_fs::create_dir(tmp.path().join("9.5.0")).unwrap();
+        std_fs::create_dir(tmp.path().join("moldau-tmp.abc")).unwrap();
+
+        assert_eq!(
+            incomplete_versions_in(tmp.path()).await.unwrap(),
+            vec![semver::Version::new(9, 5, 0)]
+        );
+        assert!(
+            incomplete_versions_in(&tmp.path().join("missing"))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
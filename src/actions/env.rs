@@ -0,0 +1,52 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use clap_complete::Shell;
+use eyre::{Result, bail, eyre};
+
+use crate::dirs;
+
+fn quote_posix(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', r"\\")
+            .replace('"', "\\\"")
+            .replace('$', r"\$")
+            .replace('`', r"\`")
+    )
+}
+
+fn quote_fish(s: &str) -> String {
+    format!("'{}'", s.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn quote_single(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+pub fn env(shell: Option<Shell>) -> Result<()> {
+    let shell = shell.or_else(Shell::from_env).ok_or_else(|| {
+        eyre!("could not detect shell from `SHELL`, please specify one explicitly")
+    })?;
+
+    let shims = dirs::shims();
+    let shims = shims
+        .to_str()
+        .ok_or_else(|| eyre!("shims directory {} is not valid UTF-8", shims.display()))?;
+
+    let line = match shell {
+        Shell::Bash | Shell::Zsh => format!("export PATH={}:\"$PATH\"", quote_posix(shims)),
+        Shell::Fish => format!("set -gx PATH {} $PATH", quote_fish(shims)),
+        Shell::PowerShell => format!(
+            "$env:PATH = {} + [IO.Path]::PathSeparator + $env:PATH",
+            quote_single(shims)
+        ),
+        Shell::Elvish => format!("set paths = [{} $@paths]", quote_single(shims)),
+        _ => bail!("unsupported shell: {shell}"),
+    };
+
+    println!("{line}");
+
+    Ok(())
+}
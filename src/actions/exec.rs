@@ -2,29 +2,196 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::env;
-use tokio::process::Command;
+use std::{
+    collections::HashMap,
+    env, iter,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Instant,
+};
+use tokio::{fs, process::Command};
 
-use eyre::{Result, eyre};
-use log::error;
-use owo_colors::colors::Red;
+use eyre::{Result, WrapErr as _, bail, eyre};
+use log::{debug, error, warn};
+use owo_colors::colors::{Red, Yellow};
+use tempdir::TempDir;
 
 use crate::{
-    models::{Spec, SpecBin, SpecName, SpecVersion},
-    util::{ExitCodeError, LogDisplay as _},
+    actions::{fetch_spec_expecting, fetch_spec_uncached, prepare},
+    config,
+    models::{PackageJsonEnginesOnly, Spec, SpecBin, SpecName, SpecVersion, SpecVersionIntegrity},
+    util::{self, ExitCodeError, LogDisplay as _},
 };
 
-pub async fn exec(bin: SpecBin, args: &[String], spec: Option<&Spec>) -> Result<bool> {
+#[cfg(unix)]
+async fn ensure_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let mut permissions = fs::metadata(path).await?.permissions();
+
+    if permissions.mode() & 0o111 == 0 {
+        warn!(
+            "{} is not executable, restoring its executable permission",
+            path.display()
+        );
+
+        permissions.set_mode(permissions.mode() | 0o111);
+        fs::set_permissions(path, permissions)
+            .await
+            .wrap_err_with(|| {
+                format!(
+                    "failed to make {} executable (is the cache read-only?)",
+                    path.display()
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+#[expect(clippy::unused_async)]
+async fn ensure_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Check a version against an npm-style range, returning `None` if the range
+/// uses syntax that the `semver` crate does not understand.
+fn npm_range_matches(range: &str, version: &semver::Version) -> Option<bool> {
+    let mut matched = false;
+
+    for alternative in range.split("||") {
+        let alternative = alternative
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let req = if alternative.is_empty() {
+            semver::VersionReq::STAR
+        } else {
+            semver::VersionReq::parse(&alternative).ok()?
+        };
+
+        matched |= req.matches(version);
+    }
+
+    Some(matched)
+}
+
+async fn check_engines(node: &Path, cache_path: &Path, spec: &Spec) {
+    let Ok(package_json) = fs::read(cache_path.join("package.json")).await else {
+        return;
+    };
+
+    let Some(range) = serde_json::from_slice::<PackageJsonEnginesOnly>(&package_json)
+        .ok()
+        .and_then(|p| p.engines.get("node").cloned())
+    else {
+        return;
+    };
+
+    let Some(node_version) = Command::new(node)
+        .arg("--version")
+        .output()
+        .await
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|stdout| semver::Version::parse(stdout.trim().trim_start_matches('v')).ok())
+    else {
+        debug!("could not detect version of {}", node.display());
+        return;
+    };
+
+    match npm_range_matches(&range, &node_version) {
+        Some(true) => {}
+        Some(false) => warn!(
+            "{} requires Node.js {}, but the current version is {}",
+            spec.log_display::<Yellow>(),
+            range.log_display::<Yellow>(),
+            node_version.log_display::<Yellow>()
+        ),
+        None => debug!("could not parse `engines.node` range {range:?} of {spec}"),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExecOptions {
+    pub spec: Option<Spec>,
+    pub cwd: Option<PathBuf>,
+    pub node: Option<PathBuf>,
+    pub env_file: Option<PathBuf>,
+    pub expect_integrity: Option<SpecVersionIntegrity>,
+    pub locked: bool,
+    pub print_path: bool,
+}
+
+/// Nested executions are normal (e.g. package scripts that run other package
+/// managers), but this many most likely means that a shim runs itself.
+const MAX_EXEC_DEPTH: u32 = 10;
+const EXEC_DEPTH_VAR: &str = "MOLDAU_EXEC_DEPTH";
+
+fn exec_depth(bin: SpecBin, value: Option<&str>) -> Result<u32> {
+    let depth: u32 = value.and_then(|depth| depth.parse().ok()).unwrap_or(0);
+
+    if depth >= MAX_EXEC_DEPTH {
+        bail!(
+            "{bin} was executed recursively {depth} times; check that the package manager does not resolve to a moldau shim itself (e.g. `PATH` contains the shims directory in an unexpected place)"
+        );
+    }
+
+    Ok(depth)
+}
+
+/// Select the exact spec pinned by the project for `--locked`, refusing
+/// anything that would have to be resolved against the registry.
+async fn locked_spec(bin: SpecBin, cwd: &Path) -> Result<Spec> {
+    let Some(spec) = Spec::parse_in(cwd, true).await? else {
+        bail!(
+            "`--locked` requires the project to pin a package manager in `packageManager` or `devEngines.packageManager`"
+        );
+    };
+
+    if !spec.version.is_exact() {
+        bail!("`--locked` requires an exact version, but the project pins {spec:#}");
+    }
+
+    if spec.name != bin.to_name() {
+        bail!("{bin} is not pinned by the project, which pins {spec:#}");
+    }
+
+    Ok(spec)
+}
+
+/// Select the spec to execute `bin` with, or `None` if `bin` is not available
+/// in the configured package manager.
+async fn select_spec(
+    bin: SpecBin,
+    args: &[String],
+    options: &ExecOptions,
+    cwd: &Path,
+) -> Result<Option<Spec>> {
+    if options.locked {
+        return locked_spec(bin, cwd).await.map(Some);
+    }
+
     let bin_default_spec = Spec {
         name: bin.to_name(),
         version: SpecVersion::default(),
     };
 
-    let mut spec = match spec {
-        Some(v) => v.to_owned(),
-        None => Spec::parse(true)
+    let mut spec = if let Some(spec) = &options.spec {
+        spec.to_owned()
+    } else if let Some(spec) = &config::get().force_spec {
+        warn!(
+            "using forced package manager {} (ignoring project configuration)",
+            spec.log_display::<Yellow>()
+        );
+
+        spec.to_owned()
+    } else {
+        Spec::parse_in(cwd, true)
             .await?
-            .unwrap_or_else(|| bin_default_spec.clone()),
+            .unwrap_or_else(|| bin_default_spec.clone())
     };
 
     if spec.name != bin_default_spec.name {
@@ -57,19 +224,164 @@ pub async fn exec(bin: SpecBin, args: &[String], spec: Option<&Spec>) -> Result<
                 spec.log_display::<Red>()
             );
 
-            return Ok(false);
+            return Ok(None);
         }
     }
 
-    let (cache_path, bins) = super::prepare(&spec).await?;
+    Ok(Some(spec))
+}
 
+/// Absolute path of the script for `bin`, which has to be inside the package.
+async fn script_path(
+    bin: SpecBin,
+    spec: &Spec,
+    cache_path: &Path,
+    bins: &HashMap<String, String>,
+) -> Result<PathBuf> {
     let bin_path = bins
         .get(&bin.to_string())
         .ok_or_else(|| eyre!("could not obtain path of {bin:?} in {spec}"))?;
 
-    let status = Command::new("node")
-        .arg(cache_path.join(bin_path))
+    // The package manager may run in a different directory than moldau, so
+    // the script path must not depend on moldau's working directory
+    let script_path = std::path::absolute(cache_path.join(bin_path))?;
+
+    if !script_path.is_file() {
+        bail!(
+            "{} does not exist; the cached {spec:#} may be corrupted, try running `moldau clean --all`",
+            script_path.display()
+        );
+    }
+
+    // A malicious package could point its bins at arbitrary executables, e.g.
+    // with `../../../../bin/sh`. Symlinks within the package are fine.
+    if !util::is_within(cache_path, &script_path)? {
+        if let Ok(target) = fs::read_link(&script_path).await {
+            bail!(
+                "bin {bin_path:?} of {spec:#} is a symlink to {}, which is outside of the package",
+                target.display()
+            );
+        }
+
+        bail!(
+            "bin {bin_path:?} of {spec:#} resolves to {}, which is outside of the package",
+            script_path.display()
+        );
+    }
+
+    Ok(script_path)
+}
+
+pub async fn exec(bin: SpecBin, args: &[String], options: &ExecOptions) -> Result<bool> {
+    let start = Instant::now();
+    let depth = exec_depth(bin, env::var(EXEC_DEPTH_VAR).ok().as_deref())?;
+
+    let cwd = match &options.cwd {
+        Some(cwd) => env::current_dir()?.join(cwd),
+        None => env::current_dir()?,
+    };
+
+    let Some(spec) = select_spec(bin, args, options, &cwd).await? else {
+        return Ok(false);
+    };
+
+    let env_vars = match &options.env_file {
+        Some(path) => {
+            let contents = fs::read_to_string(path)
+                .await
+                .wrap_err_with(|| format!("failed to read env file {}", path.display()))?;
+
+            util::parse_dotenv(&contents)
+                .wrap_err_with(|| format!("failed to parse env file {}", path.display()))?
+        }
+        None => Vec::new(),
+    };
+
+    // Without the cache, the package manager lives in a temporary directory
+    // that has to outlive its execution
+    let mut temp_dir = None;
+
+    let expected = options.expect_integrity.as_ref();
+
+    let (cache_path, bins) = if config::get().no_cache() {
+        let dir = temp_dir.insert(TempDir::new("moldau")?);
+        fetch_spec_uncached(&spec, dir.path(), expected).await?
+    } else if expected.is_some() {
+        // The expected integrity can only be checked against a resolved
+        // version, so this skips looking for cached matches of ranges
+        fetch_spec_expecting(&spec, expected).await?
+    } else {
+        prepare(&spec).await?
+    };
+
+    let script_path = script_path(bin, &spec, &cache_path, &bins).await?;
+
+    if options.print_path {
+        println!("{}", script_path.display());
+        return Ok(true);
+    }
+
+    let mut command = if config::get().use_standalone(spec.name) {
+        ensure_executable(&script_path).await?;
+
+        let mut command = Command::new(script_path);
+
+        // The standalone executable has no separate `pnpx` entry point
+        if bin == SpecBin::Pnpx {
+            command.arg("dlx");
+        }
+
+        command
+    } else {
+        // Package manager bins are scripts that we run with Node.js explicitly,
+        // so their executable bit and shebang do not matter
+        let custom_node = options.node.as_ref().or(config::get().node.as_ref());
+
+        let node = match custom_node {
+            Some(node) => which::which(node)
+                .map_err(|_| eyre!("{} is not an executable Node.js runtime", node.display()))?,
+            None => which::which("node").map_err(|_| {
+                eyre!("could not find `node` in PATH, which is required to run {spec:#}")
+            })?,
+        };
+
+        if config::get().engines_check() {
+            check_engines(&node, &cache_path, &spec).await;
+        }
+
+        let mut command = Command::new(&node);
+
+        // Put the selected Node.js first in PATH as well, so that scripts run
+        // by the package manager use the same runtime
+        if custom_node.is_some()
+            && let Some(node_dir) = node.parent()
+        {
+            let path = env::var_os("PATH").unwrap_or_default();
+            command.env(
+                "PATH",
+                env::join_paths(iter::once(node_dir.to_path_buf()).chain(env::split_paths(&path)))?,
+            );
+        }
+
+        command.arg(script_path);
+        command
+    };
+
+    debug!(
+        "prepared {spec} for execution in {}",
+        util::format_duration(start.elapsed())
+    );
+
+    // Package managers can prompt interactively, e.g. to approve builds, so
+    // they get the terminal as is
+    let status = command
         .args(args)
+        .current_dir(&cwd)
+        .envs(env_vars)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .env(EXEC_DEPTH_VAR, (depth + 1).to_string())
         .status()
         .await?;
 
@@ -80,3 +392,175 @@ pub async fn exec(bin: SpecBin, args: &[String], spec: Option<&Spec>) -> Result<
 
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs as std_fs;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn executable_permission_is_restored() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let path = tmp.path().join("pnpm");
+
+        std_fs::write(&path, "").unwrap();
+        std_fs::set_permissions(&path, std_fs::Permissions::from_mode(0o644)).unwrap();
+
+        ensure_executable(&path).await.unwrap();
+
+        let mode = std_fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn executable_binaries_are_not_modified() {
+        use std::os::unix::fs::{MetadataExt as _, PermissionsExt as _};
+
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let path = tmp.path().join("pnpm");
+
+        std_fs::write(&path, "").unwrap();
+        std_fs::set_permissions(&path, std_fs::Permissions::from_mode(0o755)).unwrap();
+        let before = std_fs::metadata(&path).unwrap();
+
+        ensure_executable(&path).await.unwrap();
+
+        let after = std_fs::metadata(&path).unwrap();
+        assert_eq!(
+            (before.ctime(), before.ctime_nsec()),
+            (after.ctime(), after.ctime_nsec())
+        );
+    }
+
+    #[test]
+    fn recursive_executions_are_stopped() {
+        assert_eq!(exec_depth(SpecBin::Pnpm, None).unwrap(), 0);
+        assert_eq!(exec_depth(SpecBin::Pnpm, Some("invalid")).unwrap(), 0);
+        assert_eq!(exec_depth(SpecBin::Pnpm, Some("3")).unwrap(), 3);
+        assert!(exec_depth(SpecBin::Pnpm, Some("10")).is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_binaries_are_reported() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let spec: Spec = "pnpm@9.4.0".parse().unwrap();
+        let bins = HashMap::from([("pnpm".to_owned(), "bin/pnpm.cjs".to_owned())]);
+
+        let err = script_path(SpecBin::Pnpm, &spec, tmp.path(), &bins)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("pnpm.cjs does not exist"));
+
+        std_fs::create_dir(tmp.path().join("bin")).unwrap();
+        std_fs::write(tmp.path().join("bin").join("pnpm.cjs"), "").unwrap();
+
+        let path = script_path(SpecBin::Pnpm, &spec, tmp.path(), &bins)
+            .await
+            .unwrap();
+        assert!(path.is_absolute());
+    }
+
+    #[tokio::test]
+    async fn binaries_must_stay_in_the_package() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let package = tmp.path().join("9.4.0").join("package");
+        std_fs::create_dir_all(package.join("bin")).unwrap();
+        std_fs::write(package.join("bin").join("pnpm.cjs"), "").unwrap();
+
+        let outside = tmp.path().join("outside");
+        std_fs::write(&outside, "").unwrap();
+
+        let spec: Spec = "pnpm@9.4.0".parse().unwrap();
+        let script_path = |bin_path: &str| {
+            let bins = HashMap::from([("pnpm".to_owned(), bin_path.to_owned())]);
+            let package = package.clone();
+            let spec = spec.clone();
+
+            async move { script_path(SpecBin::Pnpm, &spec, &package, &bins).await }
+        };
+
+        let err = script_path("../../outside").await.unwrap_err();
+        assert!(err.to_string().contains("outside of the package"));
+
+        let err = script_path(&outside.to_string_lossy()).await.unwrap_err();
+        assert!(err.to_string().contains("outside of the package"));
+
+        let path = script_path("bin/pnpm.cjs").await.unwrap();
+        assert_eq!(path, package.join("bin").join("pnpm.cjs"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinked_binaries_must_stay_in_the_package() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let package = tmp.path().join("package");
+        std_fs::create_dir_all(package.join("bin")).unwrap();
+        std_fs::create_dir(package.join("dist")).unwrap();
+        std_fs::write(package.join("dist").join("pnpm.cjs"), "").unwrap();
+        std_fs::write(tmp.path().join("outside.cjs"), "").unwrap();
+
+        let spec: Spec = "pnpm@9.4.0".parse().unwrap();
+        let bins = HashMap::from([("pnpm".to_owned(), "bin/pnpm.cjs".to_owned())]);
+
+        std::os::unix::fs::symlink("../dist/pnpm.cjs", package.join("bin").join("pnpm.cjs"))
+            .unwrap();
+        assert!(
+            script_path(SpecBin::Pnpm, &spec, &package, &bins)
+                .await
+                .is_ok()
+        );
+
+        std_fs::remove_file(package.join("bin").join("pnpm.cjs")).unwrap();
+        std::os::unix::fs::symlink("../../outside.cjs", package.join("bin").join("pnpm.cjs"))
+            .unwrap();
+        let err = script_path(SpecBin::Pnpm, &spec, &package, &bins)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("is a symlink to ../../outside.cjs")
+        );
+    }
+
+    #[tokio::test]
+    async fn locked_requires_an_exact_pin() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        std_fs::create_dir(tmp.path().join(".git")).unwrap();
+
+        let locked = |file: &str, contents: &str, bin| {
+            let _ = std_fs::remove_file(tmp.path().join("package.json"));
+            let _ = std_fs::remove_file(tmp.path().join(".moldaurc"));
+            std_fs::write(tmp.path().join(file), contents).unwrap();
+
+            locked_spec(bin, tmp.path())
+        };
+
+        let pinned = r#"{ "packageManager": "pnpm@9.4.0" }"#;
+        assert_eq!(
+            locked("package.json", pinned, SpecBin::Pnpm).await.unwrap(),
+            "pnpm@9.4.0".parse().unwrap()
+        );
+        assert!(locked("package.json", pinned, SpecBin::Pnpx).await.is_ok());
+
+        let err = locked("package.json", pinned, SpecBin::Yarn)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("is not pinned by the project"));
+
+        let err = locked(".moldaurc", "pnpm@^9.4.0", SpecBin::Pnpm)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("requires an exact version"));
+
+        let err = locked("package.json", "{}", SpecBin::Pnpm)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("requires the project to pin"));
+    }
+}
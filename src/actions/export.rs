@@ -0,0 +1,76 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use eyre::Result;
+use log::info;
+use owo_colors::{OwoColorize as _, colors::Blue};
+
+use crate::{
+    actions::{download_verified, spec_integrity},
+    cache,
+    models::{CacheManifest, CacheManifestEntry, NpmVersion, Spec, SpecName, SpecVersion},
+    util::LogDisplay as _,
+};
+
+pub async fn export(out: &Path, with_tarballs: bool) -> Result<()> {
+    let out_dir = out.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(out_dir).await?;
+
+    let mut manifest = CacheManifest::default();
+
+    for name in SpecName::VARIANTS {
+        let versions_dir = cache::versions_dir(*name);
+
+        for mut version in cache::cached_versions(*name).await? {
+            if !cache::is_complete(&versions_dir.join(version.to_string())) {
+                continue;
+            }
+
+            let spec = Spec {
+                name: *name,
+                version: SpecVersion::Exact(version.clone()),
+            };
+
+            let version_data = NpmVersion::fetch(&spec).await?;
+            version.build = semver::BuildMetadata::new(
+                &spec_integrity(&spec, &version_data, None)
+                    .await?
+                    .to_string(),
+            )?;
+
+            let tarball = if with_tarballs {
+                let bytes = download_verified(&version_data).await?;
+                let file_name = PathBuf::from(format!("{}-{}.tgz", name, version_data.version));
+
+                fs::write(out_dir.join(&file_name), &bytes).await?;
+                Some(file_name)
+            } else {
+                None
+            };
+
+            let spec = Spec {
+                name: *name,
+                version: SpecVersion::Exact(version),
+            };
+
+            info!("exported {:#}", spec.log_display::<Blue>());
+            manifest.entries.push(CacheManifestEntry { spec, tarball });
+        }
+    }
+
+    let mut data = serde_json::to_string_pretty(&manifest)?;
+    data.push('\n');
+    fs::write(out, data).await?;
+
+    info!(
+        "wrote {} entries to {}",
+        manifest.entries.len().green(),
+        out.display().log_display::<Blue>()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::Path;
+
+use eyre::{Result, WrapErr as _, bail};
+use log::info;
+use owo_colors::{OwoColorize as _, colors::Blue};
+
+use crate::{actions::fetch_spec, models::Spec, util, util::LogDisplay as _};
+
+pub async fn extract(spec: Option<&Spec>, output: &Path) -> Result<()> {
+    let spec = match spec {
+        Some(spec) => spec,
+        None => &match Spec::parse(true).await? {
+            Some(spec) => spec,
+            None => bail!("no `packageManager` or `devEngines.packageManager` configured!"),
+        },
+    };
+
+    if output.exists() {
+        bail!("{} already exists", output.display());
+    }
+
+    let (cache_path, _) = fetch_spec(spec).await?;
+
+    let (from, to) = (cache_path.clone(), output.to_path_buf());
+    tokio::task::spawn_blocking(move || util::copy_dir_all(&from, &to))
+        .await?
+        .wrap_err_with(|| {
+            format!(
+                "failed to copy {} to {}",
+                cache_path.display(),
+                output.display()
+            )
+        })?;
+
+    info!(
+        "extracted {} to {}",
+        spec.log_display::<Blue>(),
+        output.display().green()
+    );
+
+    Ok(())
+}
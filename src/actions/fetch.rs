@@ -2,23 +2,38 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    time::Instant,
+};
 use tokio::fs;
 
-use eyre::{Result, bail};
-use log::warn;
-use owo_colors::colors::Blue;
+use aws_lc_rs::digest;
+use eyre::{Result, bail, eyre};
+use log::{debug, warn};
+use owo_colors::colors::{Blue, Yellow};
 
 use flate2::bufread::GzDecoder;
 use tempdir::TempDir;
 
 use crate::{
-    dirs,
-    models::{NpmPackage, NpmVersion, PackageJsonBinOnly, Spec, SpecVersion},
-    util::{self, LogDisplay as _},
+    cache, config,
+    models::{
+        NpmPackage, NpmVersion, PackageJsonBinOnly, Spec, SpecName, SpecVersion,
+        SpecVersionIntegrity,
+    },
+    platform,
+    util::{self, LogDisplay as _, ProgressEvent, VerificationError},
 };
 
-async fn resolve(spec: &Spec) -> Result<NpmVersion> {
+/// Resolve a spec to the version it refers to in the registry.
+///
+/// # Errors
+///
+/// Fails if the registry cannot be reached, returns invalid metadata, or has
+/// no version matching the spec.
+pub async fn resolve(spec: &Spec) -> Result<NpmVersion> {
     match &spec.version {
         SpecVersion::Exact(_) => {
             let version_data = NpmVersion::fetch(spec).await?;
@@ -28,7 +43,9 @@ async fn resolve(spec: &Spec) -> Result<NpmVersion> {
         SpecVersion::SemverReq(req) => {
             let package = NpmPackage::fetch(spec).await?;
 
-            let Some(matching_version) = package.find_version_req(req) else {
+            let Some(matching_version) =
+                package.find_version_req(req, config::get().include_prerelease())
+            else {
                 bail!("could not find matching version for {spec}");
             };
 
@@ -47,46 +64,725 @@ async fn resolve(spec: &Spec) -> Result<NpmVersion> {
     }
 }
 
-pub async fn fetch_version(
-    spec: &Spec,
+/// Read a retained tarball along with its validators, if both exist and the
+/// tarball is intact.
+async fn read_retained(
     version: &NpmVersion,
+    tarball_path: &Path,
+    validators_path: &Path,
+) -> Result<Option<(Vec<u8>, util::CacheValidators)>> {
+    let retained = match (
+        fs::read(tarball_path).await,
+        fs::read(validators_path).await,
+    ) {
+        // A corrupt or partially written tarball is downloaded again rather than
+        // failing verification later on
+        (Ok(bytes), Ok(validators)) if version.integrity()?.verify(&bytes).is_ok() => {
+            serde_json::from_slice::<util::CacheValidators>(&validators)
+                .ok()
+                .map(|validators| (bytes, validators))
+        }
+        (Ok(_), Ok(_)) => {
+            debug!("retained tarball for {version} is corrupt, downloading it again");
+            None
+        }
+        _ => None,
+    };
+
+    Ok(retained)
+}
+
+/// Download a tarball, revalidating a retained copy if there is one. The
+/// integrity of the tarball is verified regardless of where it came from.
+async fn download_retained(version: &NpmVersion) -> Result<Vec<u8>> {
+    let (tarball_path, validators_path) = cache::tarball_paths(&version.name, &version.version);
+    let retained = read_retained(version, &tarball_path, &validators_path).await?;
+
+    let downloaded = util::download_if_modified(
+        &version.to_string(),
+        &version.dist.tarball,
+        version.tarball_headers()?,
+        retained.as_ref().map(|(_, validators)| validators),
+    )
+    .await?;
+
+    let Some((bytes, validators)) = downloaded else {
+        debug!("using retained tarball for {version}");
+        return retained
+            .map(|(bytes, _)| bytes)
+            .ok_or_else(|| eyre!("no retained tarball for {version}"));
+    };
+
+    fs::create_dir_all(cache::tarballs_dir()).await?;
+    util::write_atomic(&tarball_path, &bytes).await?;
+    util::write_atomic(&validators_path, serde_json::to_vec(&validators)?).await?;
+
+    Ok(bytes)
+}
+
+pub async fn download_verified(version: &NpmVersion) -> Result<Vec<u8>> {
+    let start = Instant::now();
+
+    let bytes = if config::get().keep_tarballs() {
+        download_retained(version).await?
+    } else {
+        util::download(
+            &version.to_string(),
+            &version.dist.tarball,
+            version.tarball_headers()?,
+        )
+        .await?
+    };
+
+    debug!(
+        "downloaded {version} in {}",
+        util::format_duration(start.elapsed())
+    );
+
+    let start = Instant::now();
+    let config = config::get();
+
+    if config.no_verify_integrity {
+        warn!(
+            "integrity verification is disabled, not verifying {}",
+            version.log_display::<Yellow>()
+        );
+    } else {
+        version.verify_integrity(&bytes)?;
+    }
+
+    if config.no_verify_signature {
+        warn!(
+            "signature verification is disabled, not verifying {}",
+            version.log_display::<Yellow>()
+        );
+    } else {
+        version.verify_signature()?;
+    }
+
+    debug!(
+        "verified {version} in {}",
+        util::format_duration(start.elapsed())
+    );
+
+    if !config.no_verify_integrity {
+        util::emit(&ProgressEvent::Verified {
+            package: &version.name,
+            version: &version.version,
+        });
+    }
+
+    Ok(bytes)
+}
+
+/// Check a downloaded tarball against an integrity given on the command line,
+/// before anything is extracted from it.
+fn verify_expected_integrity(
+    version: &NpmVersion,
+    bytes: &[u8],
+    expected: Option<&SpecVersionIntegrity>,
+) -> Result<()> {
+    if let Some(expected) = expected {
+        if let Err((expected, actual)) = expected.verify(bytes) {
+            bail!(VerificationError(format!(
+                "integrity (expected) failed to verify for {version} (expected: {expected}, actual: {actual})"
+            )));
+        }
+
+        debug!("integrity (expected) verified for {version}");
+    }
+
+    Ok(())
+}
+
+/// Check a cached version against an integrity given on the command line. The
+/// tarball isn't around anymore, but it was verified against the registry
+/// integrity when it was fetched.
+fn verify_expected_integrity_cached(
+    version: &NpmVersion,
+    expected: Option<&SpecVersionIntegrity>,
+) -> Result<()> {
+    if let Some(expected) = expected {
+        let registry = version.integrity()?;
+
+        if registry.algorithm() != expected.algorithm() {
+            bail!(
+                "cannot verify the expected integrity of the cached {version}, which was verified with {registry} by the registry"
+            );
+        }
+
+        if &registry != expected {
+            bail!(VerificationError(format!(
+                "integrity (expected) failed to verify for {version} (expected: {expected}, registry: {registry})"
+            )));
+        }
+
+        debug!("integrity (expected) verified for cached {version}");
+    }
+
+    Ok(())
+}
+
+/// Whether a link at `path` (relative to the archive root) with `target` stays
+/// inside the package, which is the top-level directory of the archive (e.g.
+/// `package/`). Symlink targets are relative to the link's directory, and hard
+/// link targets to the archive root.
+fn link_stays_within(path: &Path, target: &Path, is_symlink: bool) -> bool {
+    let Some(Component::Normal(root)) = path.components().next() else {
+        return false;
+    };
+
+    let mut target = target.components();
+
+    let base = if is_symlink {
+        path.parent().map(|parent| parent.components().skip(1))
+    } else {
+        if target.next() != Some(Component::Normal(root)) {
+            return false;
+        }
+
+        None
+    };
+
+    let mut depth = 0_usize;
+
+    for component in base.into_iter().flatten().chain(target) {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+
+    true
+}
+
+/// Reject archives with links that point outside of the package, which could
+/// otherwise be used to make bins run arbitrary executables.
+fn check_links(bytes: &[u8]) -> Result<()> {
+    for entry in tar::Archive::new(GzDecoder::new(bytes)).entries()? {
+        let entry = entry?;
+        let entry_type = entry.header().entry_type();
+
+        if (entry_type.is_symlink() || entry_type.is_hard_link())
+            && let Some(target) = entry.link_name()?
+            && !link_stays_within(&entry.path()?, &target, entry_type.is_symlink())
+        {
+            bail!(
+                "archive entry {} links to {}, which is outside of the package",
+                entry.path()?.display(),
+                target.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Extract a package into `dir`, verifying it against `spec`, and return the
+/// root of the package along with its bins.
+pub async fn extract(
+    label: &str,
+    spec: Option<&Spec>,
+    bytes: &[u8],
+    dir: &Path,
 ) -> Result<(PathBuf, HashMap<String, String>)> {
-    let cache_versions_dir = dirs::cache().join("versions").join(spec.name.to_string());
-    fs::create_dir_all(&cache_versions_dir).await?;
+    // A tampered tarball should never be extracted, so everything that can be
+    // verified from the tarball alone is checked first
+    let verify_spec = spec.filter(|_| !config::get().no_verify_integrity);
+
+    if let Some(spec) = verify_spec {
+        spec.verify_tarball_integrity(bytes)?;
+    }
+
+    let start = Instant::now();
+    util::emit(&ProgressEvent::Extracting { name: label });
+    let spinner = util::Spinner::start(label, "extracting")?;
+
+    check_links(bytes)?;
+    tar::Archive::new(GzDecoder::new(bytes)).unpack(dir)?;
+    let root = util::find_root(dir).await?;
+
+    spinner.finish();
+
+    debug!(
+        "extracted {label} in {}",
+        util::format_duration(start.elapsed())
+    );
+
+    let package_json = fs::read(root.join("package.json")).await?;
+    let PackageJsonBinOnly { bin } = serde_json::from_slice(&package_json)?;
+
+    if let Some(spec) = verify_spec {
+        spec.verify_bin_integrity(&root, &bin).await?;
+    }
+
+    Ok((root.into_owned(), bin))
+}
+
+pub async fn unpack(
+    label: &str,
+    spec: Option<&Spec>,
+    bytes: &[u8],
+    cache_dir: &Path,
+) -> Result<HashMap<String, String>> {
+    cache::remove_stale_temp_dirs().await?;
 
-    let cache_dir = cache_versions_dir.join(&version.version);
+    // If the temporary directory is on a different filesystem than the cache,
+    // moving the package into place falls back to copying it
+    let tmp_dir = config::get().tmp_dir();
+    fs::create_dir_all(&tmp_dir).await?;
+    let unpack_dir = TempDir::new_in(&tmp_dir, "moldau-tmp")?;
 
-    if cache_dir.exists() {
+    let (unpack_root, bin) = extract(label, spec, bytes, unpack_dir.path()).await?;
+
+    if let Some(parent) = cache_dir.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    util::move_dir(&unpack_root, cache_dir).await?;
+    unpack_dir.close()?;
+
+    Ok(bin)
+}
+
+async fn clear_incomplete(cache_dir: &Path, version: &NpmVersion) -> Result<()> {
+    if cache_dir.exists() && !cache::is_complete(cache_dir) {
+        warn!(
+            "removing incomplete cached version {:#}",
+            version.log_display::<Blue>()
+        );
+
+        fs::remove_dir_all(&cache_dir).await?;
+    }
+
+    Ok(())
+}
+
+async fn fetch_version(
+    spec: &Spec,
+    version: &NpmVersion,
+    expected: Option<&SpecVersionIntegrity>,
+) -> Result<(PathBuf, HashMap<String, String>)> {
+    let cache_dir = cache::versions_dir(spec.name).join(&version.version);
+
+    // Cache hits must not write anything, so that read-only caches work
+    if cache::is_complete(&cache_dir) {
         warn!(
             "{:#} is already cached, not fetching",
             version.log_display::<Blue>()
         );
 
+        verify_expected_integrity_cached(version, expected)?;
+
         let package_json = fs::read(cache_dir.join("package.json")).await?;
         let PackageJsonBinOnly { bin } = serde_json::from_slice(&package_json)?;
 
         return Ok((cache_dir, bin));
     }
 
-    let unpack_dir = TempDir::new_in(dirs::cache(), "moldau-tmp")?;
+    clear_incomplete(&cache_dir, version).await?;
 
-    let bytes = util::download(&version.to_string(), &version.dist.tarball).await?;
+    let bytes = download_verified(version).await?;
+    verify_expected_integrity(version, &bytes, expected)?;
 
-    version.verify_integrity(&bytes)?;
-    version.verify_signature()?;
+    let bin = unpack(&version.to_string(), Some(spec), &bytes, &cache_dir).await?;
 
-    tar::Archive::new(GzDecoder::new(&bytes[..])).unpack(&unpack_dir)?;
-    let unpack_root = util::find_root(unpack_dir.path()).await?;
+    Ok((cache_dir, bin))
+}
 
-    spec.verify_integrity(&bytes, &unpack_root, version).await?;
+/// Resolve the standalone package corresponding to a pnpm spec.
+async fn resolve_standalone(spec: &Spec) -> Result<NpmVersion> {
+    let version = resolve(spec).await?;
 
-    fs::rename(unpack_root, &cache_dir).await?;
-    unpack_dir.close()?;
+    // The integrity in the spec refers to the regular npm package, so we can
+    // only check it against the registry metadata. The standalone package is
+    // then verified against its own registry integrity and signatures.
+    if let Some(integrity) = spec.version.integrity()?
+        && !config::get().no_verify_integrity
+    {
+        let registry_integrity = version.integrity()?;
+
+        if integrity != registry_integrity {
+            bail!(VerificationError(format!(
+                "integrity (spec) failed to verify for {spec} (expected: {integrity}, registry: {registry_integrity})"
+            )));
+        }
+    }
 
-    Ok((cache_dir, version.bin.clone()))
+    NpmVersion::fetch_package(&platform::pnpm_standalone_package()?, &version.version).await
 }
 
+/// Fetch the standalone executable for a pnpm spec into the cache, regardless
+/// of whether standalone executables are enabled.
+pub async fn fetch_standalone(
+    spec: &Spec,
+    expected: Option<&SpecVersionIntegrity>,
+) -> Result<(PathBuf, HashMap<String, String>)> {
+    let standalone_version = resolve_standalone(spec).await?;
+
+    let cache_dir = cache::standalone_versions_dir(spec.name).join(&standalone_version.version);
+
+    if cache::is_complete(&cache_dir) {
+        warn!(
+            "{:#} is already cached, not fetching",
+            standalone_version.log_display::<Blue>()
+        );
+
+        verify_expected_integrity_cached(&standalone_version, expected)?;
+    } else {
+        clear_incomplete(&cache_dir, &standalone_version).await?;
+
+        let bytes = download_verified(&standalone_version).await?;
+        verify_expected_integrity(&standalone_version, &bytes, expected)?;
+
+        unpack(&standalone_version.to_string(), None, &bytes, &cache_dir).await?;
+    }
+
+    Ok((cache_dir, platform::pnpm_standalone_bins()))
+}
+
+/// Fetch a package manager into `dir` without using the cache.
+pub async fn fetch_spec_uncached(
+    spec: &Spec,
+    dir: &Path,
+    expected: Option<&SpecVersionIntegrity>,
+) -> Result<(PathBuf, HashMap<String, String>)> {
+    if config::get().use_standalone(spec.name) {
+        let standalone_version = resolve_standalone(spec).await?;
+        let bytes = download_verified(&standalone_version).await?;
+        verify_expected_integrity(&standalone_version, &bytes, expected)?;
+
+        let (root, _) = extract(&standalone_version.to_string(), None, &bytes, dir).await?;
+
+        return Ok((root, platform::pnpm_standalone_bins()));
+    }
+
+    let version = resolve(spec).await?;
+    let bytes = download_verified(&version).await?;
+    verify_expected_integrity(&version, &bytes, expected)?;
+
+    extract(&version.to_string(), Some(spec), &bytes, dir).await
+}
+
+/// Download, verify, and cache the package manager a spec refers to,
+/// returning its directory and its bins, which map names to paths relative to
+/// the directory.
+///
+/// # Errors
+///
+/// Fails if the spec cannot be resolved, the download or any integrity or
+/// signature check fails, or the package cannot be extracted into the cache.
 pub async fn fetch_spec(spec: &Spec) -> Result<(PathBuf, HashMap<String, String>)> {
+    fetch_spec_expecting(spec, None).await
+}
+
+/// Fetch a package manager, additionally checking the downloaded tarball
+/// against `expected` before extracting it.
+pub async fn fetch_spec_expecting(
+    spec: &Spec,
+    expected: Option<&SpecVersionIntegrity>,
+) -> Result<(PathBuf, HashMap<String, String>)> {
+    if config::get().use_standalone(spec.name) {
+        return fetch_standalone(spec, expected).await;
+    }
+
+    fetch_package(spec, expected).await
+}
+
+/// Fetch the npm package for a spec into the cache, regardless of whether
+/// standalone executables are enabled.
+pub async fn fetch_package(
+    spec: &Spec,
+    expected: Option<&SpecVersionIntegrity>,
+) -> Result<(PathBuf, HashMap<String, String>)> {
+    let start = Instant::now();
+
+    util::emit(&ProgressEvent::Resolving {
+        spec: spec.to_string(),
+    });
+
     let resolved_version = resolve(spec).await?;
-    fetch_version(spec, &resolved_version).await
+
+    debug!(
+        "resolved {spec} to {resolved_version} in {}",
+        util::format_duration(start.elapsed())
+    );
+
+    util::emit(&ProgressEvent::Resolved {
+        spec: spec.to_string(),
+        package: &resolved_version.name,
+        version: &resolved_version.version,
+    });
+
+    let (cache_path, bins) = fetch_version(spec, &resolved_version, expected).await?;
+
+    util::emit(&ProgressEvent::Done {
+        spec: spec.to_string(),
+        path: &cache_path,
+    });
+
+    Ok((cache_path, bins))
+}
+
+/// Compute the integrity that `use` writes into the spec for a cached version,
+/// optionally with a specific algorithm instead of the default.
+pub async fn spec_integrity(
+    spec: &Spec,
+    version: &NpmVersion,
+    algorithm: Option<&'static digest::Algorithm>,
+) -> Result<SpecVersionIntegrity> {
+    if spec.name == SpecName::Yarn {
+        // If the package manager is Yarn, the integrity is the hash of the bin
+        // file, according to Corepack's special handling (see
+        // `Spec::verify_bin_integrity` for related details).
+
+        let (cache_path, bins) = fetch_version(spec, version, None).await?;
+        let bin_path = bins
+            .get("yarn")
+            .ok_or_else(|| eyre!("could not resolve yarn bin path in {version}"))?;
+
+        let bin_contents = fs::read(cache_path.join(bin_path)).await?;
+
+        Ok(SpecVersionIntegrity::compute(
+            algorithm.unwrap_or(&digest::SHA512),
+            &bin_contents,
+        ))
+    } else {
+        registry_integrity(version, algorithm, download_verified).await
+    }
+}
+
+/// The integrity provided by the npm registry, unless it uses a different
+/// algorithm and the tarball has to be downloaded and hashed.
+async fn registry_integrity(
+    version: &NpmVersion,
+    algorithm: Option<&'static digest::Algorithm>,
+    download: impl AsyncFnOnce(&NpmVersion) -> Result<Vec<u8>>,
+) -> Result<SpecVersionIntegrity> {
+    let integrity = version.integrity()?;
+
+    match algorithm {
+        Some(algorithm) if integrity.algorithm() != algorithm => {
+            let bytes = download(version).await?;
+            Ok(SpecVersionIntegrity::compute(algorithm, &bytes))
+        }
+        _ => Ok(integrity),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs as std_fs;
+
+    use super::*;
+
+    fn npm_version(bytes: &[u8]) -> NpmVersion {
+        let shasum = hex::encode(aws_lc_rs::digest::digest(
+            &aws_lc_rs::digest::SHA1_FOR_LEGACY_USE_ONLY,
+            bytes,
+        ));
+
+        serde_json::from_value(serde_json::json!({
+            "name": "pnpm",
+            "version": "9.4.0",
+            "dist": {
+                "tarball": "https://registry.npmjs.org/pnpm/-/pnpm-9.4.0.tgz",
+                "shasum": shasum,
+            },
+        }))
+        .unwrap()
+    }
+
+    fn tarball(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, format!("package/{path}"), *contents)
+                .unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn pnpm_tarball() -> Vec<u8> {
+        tarball(&[
+            (
+                "package.json",
+                br#"{ "name": "pnpm", "bin": { "pnpm": "bin/pnpm.cjs" } }"#,
+            ),
+            ("bin/pnpm.cjs", b""),
+        ])
+    }
+
+    #[tokio::test]
+    async fn incomplete_versions_are_cleared() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let cache_dir = tmp.path().join("9.4.0");
+
+        std_fs::create_dir_all(cache_dir.join("bin")).unwrap();
+        clear_incomplete(&cache_dir, &npm_version(b""))
+            .await
+            .unwrap();
+        assert!(!cache_dir.exists());
+
+        std_fs::create_dir_all(&cache_dir).unwrap();
+        std_fs::write(cache_dir.join("package.json"), "{}").unwrap();
+        clear_incomplete(&cache_dir, &npm_version(b""))
+            .await
+            .unwrap();
+        assert!(cache_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn registry_integrity_is_kept_for_the_same_algorithm() {
+        let spec: Spec = "pnpm@9.4.0".parse().unwrap();
+        let version = npm_version(b"moldau");
+
+        let integrity = spec_integrity(&spec, &version, Some(&digest::SHA1_FOR_LEGACY_USE_ONLY))
+            .await
+            .unwrap();
+        assert_eq!(integrity, version.integrity().unwrap());
+    }
+
+    #[tokio::test]
+    async fn existing_pins_keep_their_algorithm() {
+        use base64::prelude::{BASE64_STANDARD, Engine as _};
+
+        let bytes = pnpm_tarball();
+        let mut version = npm_version(&bytes);
+        version.dist.integrity = Some(format!(
+            "sha512-{}",
+            BASE64_STANDARD.encode(digest::digest(&digest::SHA512, &bytes))
+        ));
+
+        let pinned: Spec = format!(
+            "pnpm@9.4.0+{}",
+            SpecVersionIntegrity::compute(&digest::SHA224, b"outdated")
+        )
+        .parse()
+        .unwrap();
+        let algorithm = pinned.version.integrity().unwrap().unwrap().algorithm();
+
+        let integrity = registry_integrity(&version, Some(algorithm), async |version| {
+            version.verify_integrity(&bytes)?;
+            Ok(bytes.clone())
+        })
+        .await
+        .unwrap();
+
+        assert!(integrity.to_string().starts_with("sha224."));
+        assert_eq!(
+            integrity,
+            SpecVersionIntegrity::compute(&digest::SHA224, &bytes)
+        );
+    }
+
+    #[tokio::test]
+    async fn tarballs_are_verified_before_extraction() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let bytes = pnpm_tarball();
+
+        let wrong = SpecVersionIntegrity::compute(&digest::SHA512, b"moldau");
+        let spec: Spec = format!("pnpm@9.4.0+{wrong}").parse().unwrap();
+        let err = extract("pnpm", Some(&spec), &bytes, tmp.path())
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("integrity (spec) failed to verify")
+        );
+        assert_eq!(std_fs::read_dir(tmp.path()).unwrap().count(), 0);
+
+        let right = SpecVersionIntegrity::compute(&digest::SHA512, &bytes);
+        let spec: Spec = format!("pnpm@9.4.0+{right}").parse().unwrap();
+        let (root, bin) = extract("pnpm", Some(&spec), &bytes, tmp.path())
+            .await
+            .unwrap();
+        assert_eq!(root, tmp.path().join("package"));
+        assert_eq!(bin["pnpm"], "bin/pnpm.cjs");
+    }
+
+    #[tokio::test]
+    async fn corrupt_retained_tarballs_are_refetched() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let tarball_path = tmp.path().join("pnpm-9.4.0.tgz");
+        let validators_path = tmp.path().join("pnpm-9.4.0.json");
+
+        let bytes = pnpm_tarball();
+        let version = npm_version(&bytes);
+        std_fs::write(&validators_path, r#"{ "etag": "\"abc\"" }"#).unwrap();
+
+        std_fs::write(&tarball_path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(
+            read_retained(&version, &tarball_path, &validators_path)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        std_fs::write(&tarball_path, &bytes).unwrap();
+        let (retained, validators) = read_retained(&version, &tarball_path, &validators_path)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(retained, bytes);
+        assert_eq!(validators.etag.as_deref(), Some("\"abc\""));
+    }
+
+    #[test]
+    fn links_must_stay_within_the_package() {
+        let stays = |path: &str, target: &str, is_symlink| {
+            link_stays_within(Path::new(path), Path::new(target), is_symlink)
+        };
+
+        assert!(stays("package/bin/pnpm", "../dist/pnpm.cjs", true));
+        assert!(stays("package/bin/pnpm", "./a/../b", true));
+        assert!(!stays("package/bin/pnpm", "../../../usr/bin/node", true));
+        assert!(!stays("package/bin/pnpm", "/usr/bin/node", true));
+
+        // The package root is `package/`, not the archive root
+        assert!(!stays("package/bin/x", "../../foo", true));
+        assert!(!stays("package/x", "../package/x", true));
+
+        // Hard link targets are relative to the archive root instead
+        assert!(stays("package/bin/pnpm", "package/dist/pnpm.cjs", false));
+        assert!(!stays("package/bin/pnpm", "../dist/pnpm.cjs", false));
+        assert!(!stays("package/bin/pnpm", "other/pnpm.cjs", false));
+        assert!(!stays(
+            "package/bin/pnpm",
+            "package/../other/pnpm.cjs",
+            false
+        ));
+    }
+
+    #[test]
+    fn escaping_links_are_rejected() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        builder
+            .append_link(&mut header, "package/bin/pnpm.cjs", "../../../etc/passwd")
+            .unwrap();
+
+        let bytes = builder.into_inner().unwrap().finish().unwrap();
+        assert!(check_links(&bytes).is_err());
+        assert!(check_links(&pnpm_tarball()).is_ok());
+    }
 }
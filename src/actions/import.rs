@@ -0,0 +1,82 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::Path;
+use tokio::fs;
+
+use eyre::{Result, WrapErr as _, bail};
+use log::{info, warn};
+use owo_colors::{OwoColorize as _, colors::Blue};
+
+use crate::{
+    actions::{PrefetchOptions, prefetch::prefetch_many, unpack},
+    cache, config,
+    models::{CacheManifest, SpecVersion},
+    util::LogDisplay as _,
+};
+
+pub async fn import(manifest_path: &Path) -> Result<()> {
+    let manifest: CacheManifest = serde_json::from_slice(
+        &fs::read(manifest_path)
+            .await
+            .wrap_err_with(|| format!("failed to read {}", manifest_path.display()))?,
+    )
+    .wrap_err_with(|| format!("failed to parse {}", manifest_path.display()))?;
+
+    let base_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut specs = Vec::new();
+    let mut unpacked = 0_usize;
+
+    for entry in manifest.entries {
+        let spec = entry.spec;
+
+        if !matches!(spec.version, SpecVersion::Exact(_)) {
+            bail!("manifest entry {spec} is not an exact version");
+        }
+
+        let Some(tarball) = entry.tarball else {
+            specs.push(spec);
+            continue;
+        };
+
+        if spec.version.integrity()?.is_none() && !config::get().no_verify_integrity {
+            bail!("manifest entry {spec} has a tarball but no integrity to verify it against");
+        }
+
+        let cache_dir = cache::versions_dir(spec.name).join(format!("{:#}", spec.version));
+
+        if cache::is_complete(&cache_dir) {
+            warn!(
+                "{:#} is already cached, not importing",
+                spec.log_display::<Blue>()
+            );
+            continue;
+        }
+
+        if cache_dir.exists() {
+            fs::remove_dir_all(&cache_dir).await?;
+        }
+
+        let tarball_path = base_dir.join(&tarball);
+        let bytes = fs::read(&tarball_path)
+            .await
+            .wrap_err_with(|| format!("failed to read {}", tarball_path.display()))?;
+
+        unpack(&format!("{spec:#}"), Some(&spec), &bytes, &cache_dir).await?;
+        info!("imported {:#}", spec.log_display::<Blue>());
+        unpacked += 1;
+    }
+
+    let fetched = specs.len();
+    prefetch_many(specs, &PrefetchOptions::default()).await?;
+
+    info!(
+        "{} versions imported from tarballs, {} fetched",
+        unpacked.green(),
+        fetched.green()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::BTreeMap;
+
+use eyre::{Result, bail};
+use owo_colors::OwoColorize as _;
+use serde::Serialize;
+
+use crate::{
+    actions::resolve,
+    cache,
+    models::{NpmRepository, NpmVersion, Spec, SpecName, SpecVersion},
+};
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum SignatureStatus {
+    Verified,
+    Invalid,
+    Unsigned,
+}
+
+impl SignatureStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Verified => "verified",
+            Self::Invalid => "invalid",
+            Self::Unsigned => "unsigned",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+struct InfoEntry {
+    name: SpecName,
+    version: String,
+    package: String,
+    description: Option<String>,
+    homepage: Option<String>,
+    repository: Option<String>,
+    tarball: String,
+    integrity: String,
+    signature: SignatureStatus,
+    bins: BTreeMap<String, String>,
+    cached: bool,
+}
+
+pub async fn info(spec: Option<&Spec>, json: bool) -> Result<()> {
+    let spec = match spec {
+        Some(spec) => spec,
+        None => &match Spec::parse(true).await? {
+            Some(spec) => spec,
+            None => bail!("no `packageManager` or `devEngines.packageManager` configured!"),
+        },
+    };
+
+    let mut version = resolve(spec).await?;
+
+    // Versions resolved from abbreviated packuments lack most metadata
+    if !matches!(spec.version, SpecVersion::Exact(_)) {
+        version = NpmVersion::fetch_package(&version.name, &version.version).await?;
+    }
+
+    let signature = if version.dist.signatures.is_empty() {
+        SignatureStatus::Unsigned
+    } else if version.verify_signature().is_ok() {
+        SignatureStatus::Verified
+    } else {
+        SignatureStatus::Invalid
+    };
+
+    let entry = InfoEntry {
+        name: spec.name,
+        package: spec.to_npm_package_name(),
+        description: version.description.clone(),
+        homepage: version.homepage.clone(),
+        repository: version.repository.as_ref().and_then(NpmRepository::web_url),
+        tarball: version.dist.tarball.clone(),
+        integrity: version.integrity()?.to_string(),
+        signature,
+        bins: version.bin.clone().into_iter().collect(),
+        cached: cache::is_complete(&cache::versions_dir(spec.name).join(&version.version)),
+        version: version.version,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entry)?);
+        return Ok(());
+    }
+
+    anstream::println!(
+        "{}{}{}",
+        entry.name.blue(),
+        "@".dimmed(),
+        entry.version.blue()
+    );
+
+    if let Some(description) = &entry.description {
+        anstream::println!("{description}");
+    }
+
+    anstream::println!();
+
+    let bins = entry
+        .bins
+        .iter()
+        .map(|(bin, path)| format!("{bin} ({path})"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let fields = [
+        ("package", Some(entry.package)),
+        ("homepage", entry.homepage),
+        ("repository", entry.repository),
+        ("tarball", Some(entry.tarball)),
+        ("integrity", Some(entry.integrity)),
+        ("signature", Some(entry.signature.as_str().to_owned())),
+        ("bins", (!bins.is_empty()).then_some(bins)),
+        (
+            "cached",
+            Some(if entry.cached { "yes" } else { "no" }.to_owned()),
+        ),
+    ];
+
+    for (key, value) in fields {
+        if let Some(value) = value {
+            anstream::println!("{}  {value}", format!("{key:<10}").dimmed());
+        }
+    }
+
+    Ok(())
+}
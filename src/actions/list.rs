@@ -0,0 +1,73 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use eyre::Result;
+use indicatif::DecimalBytes;
+use owo_colors::OwoColorize as _;
+use serde::Serialize;
+
+use crate::{
+    cache,
+    models::{Spec, SpecName, SpecVersion},
+    util,
+};
+
+#[derive(Serialize, Clone, Debug)]
+struct ListEntry {
+    name: SpecName,
+    version: semver::Version,
+    size_bytes: u64,
+    is_active: bool,
+}
+
+pub async fn list(json: bool) -> Result<()> {
+    let active_spec = Spec::parse(true).await.ok().flatten();
+    let mut entries = Vec::new();
+
+    for name in SpecName::VARIANTS {
+        let versions_path = cache::versions_dir(*name);
+
+        for version in cache::cached_versions(*name).await? {
+            let size_bytes = util::dir_size(&versions_path.join(version.to_string()))?;
+
+            let is_active = active_spec.as_ref().is_some_and(|spec| {
+                spec.name == *name
+                    && matches!(&spec.version, SpecVersion::Exact(v) if v.cmp_precedence(&version).is_eq())
+            });
+
+            entries.push(ListEntry {
+                name: *name,
+                version,
+                size_bytes,
+                is_active,
+            });
+        }
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    for entry in &entries {
+        anstream::println!(
+            "{}{}{}  {}{}",
+            entry.name.blue(),
+            "@".dimmed(),
+            entry.version.blue(),
+            DecimalBytes(entry.size_bytes).dimmed(),
+            if entry.is_active {
+                format!("  {}", "(active)".green())
+            } else {
+                String::new()
+            }
+        );
+    }
+
+    if entries.is_empty() {
+        anstream::eprintln!("{}", "no package managers are cached".dimmed());
+    }
+
+    Ok(())
+}
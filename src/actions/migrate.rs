@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+use eyre::{Result, eyre};
+use log::{info, warn};
+use owo_colors::{OwoColorize as _, colors::Blue};
+use tempdir::TempDir;
+
+use crate::{
+    cache, dirs,
+    models::{PackageJsonManifest, Spec, SpecName, SpecVersion},
+    util::{self, LogDisplay as _},
+};
+
+fn corepack_home() -> Result<PathBuf> {
+    if let Some(home) = env::var_os("COREPACK_HOME") {
+        return Ok(PathBuf::from(home));
+    }
+
+    let cache_home = if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        PathBuf::from(dir)
+    } else {
+        let home = env::home_dir().ok_or_else(|| eyre!("could not determine home directory"))?;
+
+        if cfg!(windows) {
+            home.join("AppData").join("Local")
+        } else {
+            home.join(".cache")
+        }
+    };
+
+    Ok(cache_home.join("node").join("corepack"))
+}
+
+async fn check_entry(name: SpecName, version: &semver::Version, path: &Path) -> Result<(), String> {
+    let package_json = fs::read(path.join("package.json"))
+        .await
+        .map_err(|_| "missing package.json".to_owned())?;
+
+    let manifest: PackageJsonManifest = serde_json::from_slice(&package_json)
+        .map_err(|err| format!("invalid package.json: {err}"))?;
+
+    let expected_name = Spec {
+        name,
+        version: SpecVersion::Exact(version.clone()),
+    }
+    .to_npm_package_name();
+
+    if manifest.name != expected_name {
+        return Err(format!(
+            "package name {:?} does not match {expected_name:?}",
+            manifest.name
+        ));
+    }
+
+    if manifest.version != version.to_string() {
+        return Err(format!(
+            "package version {:?} does not match directory name",
+            manifest.version
+        ));
+    }
+
+    if manifest.bin.is_empty() {
+        return Err("no bins declared in package.json".to_owned());
+    }
+
+    for (bin, bin_path) in &manifest.bin {
+        if !path.join(bin_path).is_file() {
+            return Err(format!("bin {bin:?} is missing ({bin_path})"));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn migrate(from: Option<&Path>) -> Result<()> {
+    let corepack_home = match from {
+        Some(from) => from.to_path_buf(),
+        None => corepack_home()?,
+    };
+
+    let corepack_versions = corepack_home.join("v1");
+
+    if !corepack_versions.is_dir() {
+        warn!("no Corepack cache found at {}", corepack_versions.display());
+
+        return Ok(());
+    }
+
+    let (mut imported, mut skipped) = (0_usize, 0_usize);
+
+    for name in SpecName::VARIANTS {
+        let Ok(mut read_dir) = fs::read_dir(corepack_versions.join(name.to_string())).await else {
+            continue;
+        };
+
+        let versions_dir = cache::versions_dir(*name);
+
+        while let Some(entry) = read_dir.next_entry().await? {
+            let Ok(version) = semver::Version::parse(&entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+
+            let display = format!("{name}@{version}");
+            let dest = versions_dir.join(version.to_string());
+
+            if dest.exists() {
+                info!(
+                    "skipped {} {}",
+                    display.log_display::<Blue>(),
+                    "(already cached)".dimmed()
+                );
+                skipped += 1;
+                continue;
+            }
+
+            if let Err(reason) = check_entry(*name, &version, &entry.path()).await {
+                warn!(
+                    "skipped {} {}",
+                    display.log_display::<Blue>(),
+                    format!("({reason})").dimmed()
+                );
+                skipped += 1;
+                continue;
+            }
+
+            fs::create_dir_all(&versions_dir).await?;
+
+            let staging_dir = TempDir::new_in(dirs::cache(), "moldau-tmp")?;
+            let staging_path = staging_dir.path().join("contents");
+
+            let (source, staging) = (entry.path(), staging_path.clone());
+            tokio::task::spawn_blocking(move || util::copy_dir_all(&source, &staging)).await??;
+
+            util::move_dir(&staging_path, &dest).await?;
+            staging_dir.close()?;
+
+            info!("imported {}", display.log_display::<Blue>());
+            imported += 1;
+        }
+    }
+
+    info!(
+        "imported {} versions from {}, skipped {}",
+        imported.green(),
+        corepack_home.display(),
+        skipped.yellow()
+    );
+
+    Ok(())
+}
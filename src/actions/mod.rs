@@ -2,16 +2,49 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+mod bin_path;
 mod clean;
+mod completions;
+mod doctor;
+mod env;
 mod exec;
+mod export;
+mod extract;
 mod fetch;
+mod import;
+mod info;
+mod list;
+mod migrate;
+mod outdated;
+mod prefetch;
 mod prepare;
 mod shims;
+mod tags;
+mod uninstall;
 mod use_;
+mod verify;
 
-pub use clean::clean;
-pub use exec::exec;
-pub use fetch::{fetch_spec, fetch_version};
-pub use prepare::prepare;
-pub use shims::shims;
-pub use use_::use_;
+pub use bin_path::{BinPathOptions, bin_path};
+pub use clean::{CleanOptions, CleanScope, clean};
+pub use completions::{CompletionKind, complete, completions};
+pub use doctor::{DoctorOptions, doctor};
+pub use env::env;
+pub use exec::{ExecOptions, exec};
+pub use export::export;
+pub use extract::extract;
+pub use fetch::{
+    download_verified, fetch_package, fetch_spec, fetch_spec_expecting, fetch_spec_uncached,
+    fetch_standalone, resolve, spec_integrity, unpack,
+};
+pub use import::import;
+pub use info::info;
+pub use list::list;
+pub use migrate::migrate;
+pub use outdated::outdated;
+pub use prefetch::{PrefetchOptions, prefetch};
+pub use prepare::{prepare, prepare_cached};
+pub use shims::{ShimsOptions, shims};
+pub use tags::tags;
+pub use uninstall::{UninstallOptions, uninstall};
+pub use use_::{UseOptions, use_};
+pub use verify::{VerifyOptions, verify};
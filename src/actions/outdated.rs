@@ -0,0 +1,89 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use eyre::{Result, bail};
+use log::info;
+use owo_colors::colors::{Blue, Green, Yellow};
+use serde::Serialize;
+
+use crate::{
+    actions::resolve,
+    models::{NpmVersion, Spec, SpecName, SpecVersion},
+    util::LogDisplay as _,
+};
+
+#[derive(Serialize, Clone, Debug)]
+struct OutdatedEntry {
+    name: SpecName,
+    current: semver::Version,
+    latest: semver::Version,
+    up_to_date: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changelog: Option<String>,
+}
+
+pub async fn outdated(json: bool) -> Result<()> {
+    let Some(spec) = Spec::parse(true).await? else {
+        bail!("no `packageManager` or `devEngines.packageManager` configured!");
+    };
+
+    let SpecVersion::Exact(current) = &spec.version else {
+        bail!("configured package manager {spec} is not an exact version");
+    };
+
+    let mut current = current.clone();
+    current.build = semver::BuildMetadata::EMPTY;
+
+    let latest_version = resolve(&Spec {
+        name: spec.name,
+        version: SpecVersion::default(),
+    })
+    .await?;
+
+    let latest: semver::Version = latest_version.version.parse()?;
+    let up_to_date = current.cmp_precedence(&latest).is_ge();
+
+    // Abbreviated packuments omit `repository` and `homepage`, so they are
+    // fetched from the full version document
+    let changelog = if up_to_date {
+        None
+    } else {
+        NpmVersion::fetch_package(&latest_version.name, &latest_version.version)
+            .await
+            .ok()
+            .and_then(|version| version.changelog_url())
+    };
+
+    if json {
+        let entry = OutdatedEntry {
+            name: spec.name,
+            current,
+            latest,
+            up_to_date,
+            changelog,
+        };
+
+        println!("{}", serde_json::to_string_pretty(&entry)?);
+        return Ok(());
+    }
+
+    if up_to_date {
+        info!(
+            "{} is up to date",
+            format!("{}@{current}", spec.name).log_display::<Green>()
+        );
+    } else {
+        info!(
+            "{} is outdated (latest: {})",
+            format!("{}@{current}", spec.name).log_display::<Yellow>(),
+            latest.log_display::<Blue>()
+        );
+
+        if let Some(changelog) = changelog {
+            info!("changelog: {}", changelog.log_display::<Blue>());
+        }
+    }
+
+    Ok(())
+}
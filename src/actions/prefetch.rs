@@ -0,0 +1,385 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use eyre::{Result, WrapErr as _, bail};
+use log::{error, info};
+use owo_colors::{OwoColorize as _, colors::Blue};
+
+use crate::{
+    actions::{fetch_spec, fetch_spec_expecting, resolve, spec_integrity},
+    cache, config,
+    models::{NpmPackage, NpmVersion, Spec, SpecName, SpecVersion, SpecVersionIntegrity},
+    util::{self, IsVerificationError as _, LogDisplay as _},
+};
+
+/// Resolve specs to exact versions concurrently, returning the specs together
+/// with their versions and the number of specs that failed to resolve.
+async fn resolve_many(
+    specs: Vec<Spec>,
+    semaphore: &Arc<Semaphore>,
+    options: &PrefetchOptions,
+) -> Result<(Vec<(Spec, semver::Version)>, usize)> {
+    let mut resolved = Vec::with_capacity(specs.len());
+    let mut failed = 0_usize;
+    let mut tasks = JoinSet::new();
+
+    for spec in specs {
+        if let SpecVersion::Exact(version) = &spec.version {
+            let version = version.clone();
+            resolved.push((spec, version));
+            continue;
+        }
+
+        let permit = semaphore.clone().acquire_owned().await?;
+
+        tasks.spawn(async move {
+            let result = resolve(&spec)
+                .await
+                .and_then(|version| Ok(version.version.parse::<semver::Version>()?));
+            drop(permit);
+            (spec, result)
+        });
+    }
+
+    while let Some(task) = tasks.join_next().await {
+        let (spec, result) = task?;
+
+        match result {
+            Ok(version) => resolved.push((
+                Spec {
+                    name: spec.name,
+                    version: SpecVersion::Exact(version.clone()),
+                },
+                version,
+            )),
+            Err(err) => {
+                error!("failed to resolve {}: {err:?}", spec.log_display::<Blue>());
+                failed += 1;
+
+                if options.fail_fast && err.is_verification_error() {
+                    tasks.shutdown().await;
+                    bail!(
+                        "cancelled remaining fetches after {} failed verification",
+                        spec.log_display::<Blue>()
+                    );
+                }
+            }
+        }
+    }
+
+    Ok((resolved, failed))
+}
+
+pub async fn prefetch_many(specs: Vec<Spec>, options: &PrefetchOptions) -> Result<()> {
+    let start = Instant::now();
+
+    if specs.is_empty() {
+        return Ok(());
+    }
+
+    let requested = specs.len();
+
+    let concurrency = options.concurrency.map_or_else(
+        || config::get().max_concurrent_downloads(),
+        NonZeroUsize::get,
+    );
+    let semaphore = Arc::new(Semaphore::new(concurrency));
+
+    let (resolved, mut failed) = resolve_many(specs, &semaphore, options).await?;
+
+    // Specs resolving to the same version, like `pnpm@9` and `pnpm@9.4.0`,
+    // would otherwise be fetched concurrently into the same directory. Pinned
+    // integrities are kept so that they are still checked.
+    let mut unique: HashMap<(SpecName, semver::Version), Spec> = HashMap::new();
+
+    for (spec, mut version) in resolved {
+        version.build = semver::BuildMetadata::EMPTY;
+
+        match unique.entry((spec.name, version)) {
+            Entry::Vacant(entry) => {
+                entry.insert(spec);
+            }
+            Entry::Occupied(mut entry) => {
+                if entry.get().version.integrity().ok().flatten().is_none() {
+                    entry.insert(spec);
+                }
+            }
+        }
+    }
+
+    let total = unique.len();
+    let mut tasks = JoinSet::new();
+
+    for (idx, spec) in unique.into_values().enumerate() {
+        let permit = semaphore.clone().acquire_owned().await?;
+
+        if idx > 0
+            && let Some(delay) = options.delay
+        {
+            tokio::time::sleep(delay).await;
+        }
+
+        tasks.spawn(async move {
+            let result = fetch_spec(&spec).await;
+            drop(permit);
+            (spec, result)
+        });
+    }
+
+    while let Some(task) = tasks.join_next().await {
+        let (spec, result) = task?;
+
+        match result {
+            Ok(_) => info!("fetched {}", spec.log_display::<Blue>()),
+            Err(err) => {
+                error!("failed to fetch {}: {err:?}", spec.log_display::<Blue>());
+                failed += 1;
+
+                if options.fail_fast && err.is_verification_error() {
+                    // Cancelled fetches remove their temporary directories
+                    // when they are dropped
+                    tasks.shutdown().await;
+                    bail!(
+                        "cancelled remaining fetches after {} failed verification",
+                        spec.log_display::<Blue>()
+                    );
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("failed to fetch {failed} of {requested} package managers");
+    }
+
+    info!(
+        "fetched {} unique package managers in {}",
+        total.green(),
+        util::format_duration(start.elapsed())
+    );
+
+    Ok(())
+}
+
+async fn prefetch_all_cached(options: &PrefetchOptions) -> Result<()> {
+    let mut present = 0_usize;
+    let mut specs = Vec::new();
+
+    for name in SpecName::VARIANTS {
+        let versions_dir = cache::versions_dir(*name);
+
+        for version in cache::cached_versions(*name).await? {
+            if cache::is_complete(&versions_dir.join(version.to_string())) {
+                present += 1;
+            } else {
+                specs.push(Spec {
+                    name: *name,
+                    version: SpecVersion::Exact(version),
+                });
+            }
+        }
+    }
+
+    let fetched = specs.len();
+    prefetch_many(specs, options).await?;
+
+    info!(
+        "{} versions already present, {} fetched",
+        present.green(),
+        fetched.green()
+    );
+
+    Ok(())
+}
+
+async fn prefetch_stdin(options: &PrefetchOptions) -> Result<()> {
+    let input = tokio::task::spawn_blocking(|| std::io::read_to_string(std::io::stdin()))
+        .await?
+        .wrap_err("failed to read specs from stdin")?;
+
+    let specs = input
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(idx, line)| {
+            line.parse::<Spec>()
+                .wrap_err_with(|| format!("invalid spec on line {}", idx + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    prefetch_many(specs, options).await
+}
+
+async fn prefetch_last(spec: &Spec, n: usize, options: &PrefetchOptions) -> Result<()> {
+    let SpecVersion::SemverReq(req) = &spec.version else {
+        bail!("`--last` requires a version range, but {spec} is not one");
+    };
+
+    let package = NpmPackage::fetch(spec).await?;
+    let versions = package.find_latest_versions(req, config::get().include_prerelease(), n);
+
+    if versions.is_empty() {
+        bail!("could not find any versions matching {spec}");
+    }
+
+    let versions_dir = if config::get().use_standalone(spec.name) {
+        cache::standalone_versions_dir(spec.name)
+    } else {
+        cache::versions_dir(spec.name)
+    };
+
+    let mut present = 0_usize;
+    let mut specs = Vec::new();
+
+    for version in versions {
+        let spec = Spec {
+            name: spec.name,
+            version: SpecVersion::Exact(version),
+        };
+
+        if cache::is_complete(&versions_dir.join(format!("{:#}", spec.version))) {
+            info!("{} is already cached", spec.log_display::<Blue>());
+            present += 1;
+        } else {
+            specs.push(spec);
+        }
+    }
+
+    let fetched = specs.len();
+    prefetch_many(specs, options).await?;
+
+    info!(
+        "{} versions already present, {} fetched",
+        present.green(),
+        fetched.green()
+    );
+
+    Ok(())
+}
+
+async fn check_integrity(spec: &Spec) -> Result<()> {
+    let Some(pinned) = spec.version.integrity()? else {
+        bail!("{spec} is not pinned to an exact version with an integrity hash");
+    };
+
+    let version = NpmVersion::fetch(spec).await?;
+
+    // This handles Yarn's special bin hash, and only downloads the package if
+    // it isn't cached or the registry integrity uses a different algorithm
+    let actual = spec_integrity(spec, &version, Some(pinned.algorithm())).await?;
+
+    if actual != pinned {
+        bail!(
+            "integrity (spec) does not match the registry for {spec} (pinned: {pinned}, registry: {actual})"
+        );
+    }
+
+    info!(
+        "integrity of {:#} matches the registry",
+        spec.log_display::<Blue>()
+    );
+
+    Ok(())
+}
+
+/// Resolve a spec and print the version it resolves to, without downloading it.
+async fn dry_run(spec: &Spec) -> Result<()> {
+    let version = resolve(spec)
+        .await
+        .wrap_err_with(|| format!("failed to resolve {spec}"))?;
+
+    anstream::println!(
+        "{}{}{}",
+        version.name.blue(),
+        "@".dimmed(),
+        version.version.blue()
+    );
+
+    let fields = [
+        ("tarball", version.dist.tarball.clone()),
+        ("integrity", version.integrity()?.to_string()),
+    ];
+
+    for (key, value) in fields {
+        anstream::println!("{}  {value}", format!("{key:<10}").dimmed());
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug, Default)]
+pub struct PrefetchOptions {
+    pub spec: Option<Spec>,
+    pub all_cached: bool,
+    pub stdin: bool,
+    pub check_integrity_only: bool,
+    pub expect_integrity: Option<SpecVersionIntegrity>,
+    pub concurrency: Option<NonZeroUsize>,
+    pub delay: Option<Duration>,
+    pub fail_fast: bool,
+    pub last: Option<NonZeroUsize>,
+    pub dry_run: bool,
+}
+
+pub async fn prefetch(options: &PrefetchOptions) -> Result<()> {
+    if options.all_cached {
+        return prefetch_all_cached(options).await;
+    }
+
+    if options.stdin {
+        return prefetch_stdin(options).await;
+    }
+
+    let spec = match &options.spec {
+        Some(spec) => spec,
+        None => &match Spec::parse(true).await? {
+            Some(spec) => spec,
+            None => bail!("no `packageManager` or `devEngines.packageManager` configured!"),
+        },
+    };
+
+    if options.check_integrity_only {
+        return check_integrity(spec).await;
+    }
+
+    if options.dry_run {
+        return dry_run(spec).await;
+    }
+
+    if let Some(last) = options.last {
+        return prefetch_last(spec, last.get(), options).await;
+    }
+
+    info!("prefetching package manager {}", spec.log_display::<Blue>());
+
+    fetch_spec_expecting(spec, options.expect_integrity.as_ref()).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn empty_bulk_prefetch_does_nothing() {
+        let options = PrefetchOptions {
+            concurrency: NonZeroUsize::new(1),
+            fail_fast: true,
+            ..PrefetchOptions::default()
+        };
+
+        prefetch_many(Vec::new(), &options).await.unwrap();
+    }
+}
@@ -4,30 +4,61 @@
 
 use std::{
     collections::{BTreeSet, HashMap},
-    path::PathBuf,
+    io,
+    path::{Path, PathBuf},
 };
 use tokio::fs;
 
-use eyre::Result;
-use log::warn;
+use eyre::{Result, WrapErr as _};
+use log::{debug, warn};
 use owo_colors::colors::Blue;
 
 use crate::{
-    actions::fetch_spec,
-    dirs,
-    models::{PackageJsonBinOnly, Spec, SpecVersion},
+    actions::{fetch_spec, resolve},
+    cache, config,
+    models::{PackageJsonBinOnly, Spec, SpecVersion, version_req_matches},
+    platform,
     util::LogDisplay as _,
 };
 
-pub async fn prepare(spec: &Spec) -> Result<(PathBuf, HashMap<String, String>)> {
-    let cache_versions_dir = dirs::cache().join("versions").join(spec.name.to_string());
+async fn check_updates(spec: &Spec, cached: &semver::Version) {
+    match resolve(spec).await {
+        Ok(latest) => {
+            if let Ok(latest) = semver::Version::parse(&latest.version)
+                && latest.cmp_precedence(cached).is_gt()
+            {
+                warn!(
+                    "using cached {} for {}, but {} is available; run `moldau prefetch` to fetch it",
+                    cached.log_display::<Blue>(),
+                    spec.log_display::<Blue>(),
+                    latest.log_display::<Blue>()
+                );
+            }
+        }
+        Err(err) => debug!("failed to check for updates to {spec}: {err}"),
+    }
+}
 
+/// The highest cached version matching `spec`, if any.
+async fn cached_version(spec: &Spec, cache_versions_dir: &Path) -> Result<Option<semver::Version>> {
     let mut cached_ok_versions = BTreeSet::new();
 
     // There is no way of knowing if a cached version matches a dist tag
-    if !spec.version.is_dist_tag()
-        && let Ok(mut read_dir) = fs::read_dir(&cache_versions_dir).await
-    {
+    let read_dir = if spec.version.is_dist_tag() {
+        None
+    } else {
+        match fs::read_dir(&cache_versions_dir).await {
+            Ok(read_dir) => Some(read_dir),
+            // Nothing has been cached for this package manager yet
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(err)
+                    .wrap_err_with(|| format!("failed to read {}", cache_versions_dir.display()));
+            }
+        }
+    };
+
+    if let Some(mut read_dir) = read_dir {
         while let Some(entry) = read_dir.next_entry().await? {
             if let Ok(this_version) = semver::Version::parse(&entry.file_name().to_string_lossy())
                 && match &spec.version {
@@ -35,22 +66,84 @@ pub async fn prepare(spec: &Spec) -> Result<(PathBuf, HashMap<String, String>)>
                         // `Version::cmp_precedence` discards build metadata, unlike `==`
                         this_version.cmp_precedence(version).is_eq()
                     }
-                    SpecVersion::SemverReq(req) => req.matches(&this_version),
+                    SpecVersion::SemverReq(req) => version_req_matches(
+                        req,
+                        &this_version,
+                        config::get().include_prerelease(),
+                    ),
                     SpecVersion::DistTag(_) => false,
                 }
+                // Incomplete entries are left over from interrupted fetches
+                && cache::is_complete(&entry.path())
             {
                 cached_ok_versions.insert(this_version);
             }
         }
     }
 
-    if let Some(cache_ok_version) = cached_ok_versions.last() {
-        let cache_dir = cache_versions_dir.join(cache_ok_version.to_string());
+    Ok(cached_ok_versions.pop_last())
+}
 
-        let package_json = fs::read(cache_dir.join("package.json")).await?;
-        let PackageJsonBinOnly { bin } = serde_json::from_slice(&package_json)?;
+fn cache_versions_dir(spec: &Spec) -> PathBuf {
+    if config::get().use_standalone(spec.name) {
+        cache::standalone_versions_dir(spec.name)
+    } else {
+        cache::versions_dir(spec.name)
+    }
+}
 
-        return Ok((cache_dir, bin));
+async fn cached_outcome(
+    spec: &Spec,
+    cache_dir: PathBuf,
+) -> Result<(PathBuf, HashMap<String, String>)> {
+    if config::get().use_standalone(spec.name) {
+        return Ok((cache_dir, platform::pnpm_standalone_bins()));
+    }
+
+    let package_json = fs::read(cache_dir.join("package.json")).await?;
+    let PackageJsonBinOnly { bin } = serde_json::from_slice(&package_json)?;
+
+    Ok((cache_dir, bin))
+}
+
+/// Like [`prepare`], but only using the cache, returning `None` if no cached
+/// version matches `spec`.
+pub async fn prepare_cached(spec: &Spec) -> Result<Option<(PathBuf, HashMap<String, String>)>> {
+    let cache_versions_dir = cache_versions_dir(spec);
+
+    match cached_version(spec, &cache_versions_dir).await? {
+        Some(version) => Ok(Some(
+            cached_outcome(spec, cache_versions_dir.join(version.to_string())).await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+pub async fn prepare(spec: &Spec) -> Result<(PathBuf, HashMap<String, String>)> {
+    let cache_versions_dir = cache_versions_dir(spec);
+    let cached_ok_version = cached_version(spec, &cache_versions_dir).await?;
+
+    // Ranges can be kept up to date by resolving them online first, using the
+    // cache only if the registry cannot be reached
+    if config::get().prefer_online()
+        && matches!(spec.version, SpecVersion::SemverReq(_))
+        && cached_ok_version.is_some()
+    {
+        match fetch_spec(spec).await {
+            Ok(outcome) => return Ok(outcome),
+            Err(err) => warn!(
+                "failed to resolve {} online, falling back to the cache: {err}",
+                spec.log_display::<Blue>()
+            ),
+        }
+    }
+
+    if let Some(cache_ok_version) = &cached_ok_version {
+        if config::get().check_updates() && matches!(spec.version, SpecVersion::SemverReq(_)) {
+            check_updates(spec, cache_ok_version).await;
+        }
+
+        return cached_outcome(spec, cache_versions_dir.join(cache_ok_version.to_string())).await;
     }
 
     warn!("fetching package manager {}", spec.log_display::<Blue>());
@@ -58,3 +151,75 @@ pub async fn prepare(spec: &Spec) -> Result<(PathBuf, HashMap<String, String>)>
     let outcome = fetch_spec(spec).await?;
     Ok(outcome)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs as std_fs;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn create_version(dir: &Path, version: &str) {
+        let version_dir = dir.join(version);
+        std_fs::create_dir_all(&version_dir).unwrap();
+        std_fs::write(
+            version_dir.join("package.json"),
+            r#"{ "bin": { "pnpm": "bin/pnpm.cjs" } }"#,
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn incomplete_and_non_version_entries_are_ignored() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        create_version(tmp.path(), "9.4.0");
+        std_fs::create_dir(tmp.path().join("9.5.0")).unwrap();
+        std_fs::create_dir(tmp.path().join("moldau-tmp.abc")).unwrap();
+        std_fs::write(tmp.path().join(".DS_Store"), "").unwrap();
+
+        let spec: Spec = "pnpm@^9".parse().unwrap();
+        assert_eq!(
+            cached_version(&spec, tmp.path()).await.unwrap(),
+            Some(semver::Version::new(9, 4, 0))
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_versions_dir_is_empty() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+
+        let spec: Spec = "pnpm@^9".parse().unwrap();
+        assert_eq!(
+            cached_version(&spec, &tmp.path().join("pnpm"))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn unreadable_versions_dir_is_an_error() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let file = tmp.path().join("pnpm");
+        std_fs::write(&file, "").unwrap();
+
+        let spec: Spec = "pnpm@^9".parse().unwrap();
+        assert!(cached_version(&spec, &file).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn read_only_caches_are_used() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let tmp = TempDir::new("moldau-test").unwrap();
+        create_version(tmp.path(), "9.4.0");
+        std_fs::set_permissions(tmp.path(), std_fs::Permissions::from_mode(0o555)).unwrap();
+
+        let spec: Spec = "pnpm@9.4.0".parse().unwrap();
+        let version = cached_version(&spec, tmp.path()).await.unwrap().unwrap();
+        assert_eq!(version, semver::Version::new(9, 4, 0));
+
+        std_fs::set_permissions(tmp.path(), std_fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
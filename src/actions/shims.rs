@@ -8,37 +8,166 @@ use std::{
 };
 use tokio::{fs, io};
 
-use eyre::Result;
-use log::{info, warn};
+use eyre::{Result, WrapErr as _};
+use log::{debug, info, warn};
+use owo_colors::OwoColorize as _;
 
 use crate::models::SpecBin;
 
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug, Default)]
+pub struct ShimsOptions {
+    pub force: bool,
+    pub sync: bool,
+    pub wrapper: bool,
+    pub relative: bool,
+    pub copy: bool,
+}
+
+#[cfg(unix)]
+fn wrapper_contents(moldau: &Path, shim: &str) -> String {
+    format!(
+        "#!/bin/sh\nexec '{}' exec {shim} -- \"$@\"\n",
+        moldau.to_string_lossy().replace('\'', r"'\''")
+    )
+}
+
+#[cfg(unix)]
+async fn write_wrapper(moldau: &Path, shim: &SpecBin, shim_path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let contents = wrapper_contents(moldau, &shim.to_string());
+
+    if fs::symlink_metadata(shim_path).await.is_ok() {
+        if fs::read_to_string(shim_path)
+            .await
+            .is_ok_and(|existing| existing == contents)
+        {
+            return Ok(());
+        }
+
+        return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+    }
+
+    fs::write(shim_path, contents).await?;
+    fs::set_permissions(shim_path, std::fs::Permissions::from_mode(0o755)).await?;
+
+    Ok(())
+}
+
+/// Whether `path` is in the shims directory being installed into or the
+/// default one, where a `moldau` would be a shim itself rather than a stable
+/// location of the executable.
 #[cfg(unix)]
-async fn write_shim(dest: &Path, shim: &SpecBin, force: bool) -> Result<()> {
-    let current_exe = env::current_exe()?.canonicalize()?;
+fn is_in_shims_dir(path: &Path, dest: &Path) -> bool {
+    let Some(parent) = path.parent() else {
+        return false;
+    };
 
-    let moldau: PathBuf;
+    let parent = parent
+        .canonicalize()
+        .unwrap_or_else(|_| parent.to_path_buf());
+
+    [dest.to_path_buf(), crate::dirs::shims()]
+        .iter()
+        .any(|dir| dir.canonicalize().is_ok_and(|dir| dir == parent) || *dir == parent)
+}
+
+/// Path that shims should run moldau from, preferring the `moldau` in PATH if
+/// it is the running executable and not in a shims directory.
+#[cfg(unix)]
+fn moldau_path(dest: &Path) -> Result<PathBuf> {
+    let current_exe = env::current_exe()?;
+
+    // Canonicalizing fails in some container setups and if the binary was
+    // replaced while running, which shouldn't prevent installing shims
+    let current_exe = match current_exe.canonicalize() {
+        Ok(canonical) => canonical,
+        Err(err) => {
+            warn!(
+                "could not resolve the path of the running moldau executable ({err}), linking shims to {}",
+                current_exe.display()
+            );
+
+            current_exe
+        }
+    };
 
     if let Ok(which_result) = which::which_global("moldau")
-        && which_result.canonicalize().is_ok_and(|p| p == current_exe)
+        && (which_result == current_exe
+            || which_result.canonicalize().is_ok_and(|p| p == current_exe))
+        && !is_in_shims_dir(&which_result, dest)
     {
-        moldau = which_result;
-    } else {
-        moldau = current_exe;
+        return Ok(which_result);
     }
 
+    Ok(current_exe)
+}
+
+#[cfg(windows)]
+#[expect(clippy::unnecessary_wraps)]
+fn moldau_path(_dest: &Path) -> Result<PathBuf> {
+    // Windows shims run whichever `moldau` is in PATH
+    Ok(PathBuf::from("moldau"))
+}
+
+/// Path of `to` relative to the directory `from`, both of which are absolute.
+#[cfg(unix)]
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    use std::{iter, path::Component};
+
+    let from = from.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    iter::repeat_n(Component::ParentDir, from.len() - common)
+        .chain(to[common..].iter().copied())
+        .collect()
+}
+
+/// Write a shim for `shim` into `dest`, returning whether it did not exist
+/// before.
+#[cfg(unix)]
+async fn write_shim(
+    dest: &Path,
+    shim: &SpecBin,
+    moldau: &Path,
+    options: &ShimsOptions,
+) -> Result<bool> {
+    use eyre::bail;
+
     let shim_path = dest.join(shim.to_string());
+    let existed = fs::symlink_metadata(&shim_path).await.is_ok();
 
-    if force
+    // Syncing only replaces outdated shims, e.g. symlinks when switching to
+    // wrappers, and never other files with the same name
+    let replace =
+        options.force || options.sync && is_shim(&shim_path, &shim.to_string(), dest, moldau).await;
+
+    if replace
         && let Err(err) = fs::remove_file(&shim_path).await
         && err.kind() != io::ErrorKind::NotFound
     {
         return Err(err.into());
     }
 
-    if let Err(err) = fs::symlink(&moldau, &shim_path).await {
+    // Some launchers resolve symlinks before running them, which loses the
+    // shim name in `argv[0]`, so wrapper scripts are available as well
+    if options.wrapper {
+        write_wrapper(moldau, shim, &shim_path).await?;
+        return Ok(!existed);
+    }
+
+    let target = if options.relative {
+        relative_path(&dest.canonicalize()?, moldau)
+    } else {
+        moldau.to_path_buf()
+    };
+
+    if let Err(err) = fs::symlink(&target, &shim_path).await {
         if err.kind() == io::ErrorKind::AlreadyExists {
-            if !fs::read_link(&shim_path).await.is_ok_and(|p| p == moldau) {
+            if !fs::read_link(&shim_path).await.is_ok_and(|p| p == target) {
                 return Err(err.into());
             }
         } else {
@@ -46,15 +175,34 @@ async fn write_shim(dest: &Path, shim: &SpecBin, force: bool) -> Result<()> {
         }
     }
 
-    Ok(())
+    if options.relative
+        && !fs::canonicalize(&shim_path)
+            .await
+            .is_ok_and(|p| moldau.canonicalize().is_ok_and(|moldau| moldau == p))
+    {
+        bail!(
+            "{} links to {}, which does not resolve to moldau",
+            shim_path.display(),
+            target.display()
+        );
+    }
+
+    Ok(!existed)
 }
 
 #[cfg(windows)]
-async fn write_shim(dest: &Path, shim: &SpecBin, force: bool) -> Result<()> {
+async fn write_shim(
+    dest: &Path,
+    shim: &SpecBin,
+    moldau: &Path,
+    options: &ShimsOptions,
+) -> Result<bool> {
     let shim_bash_path = dest.join(shim.to_string());
     let shim_cmd_path = shim_bash_path.with_extension("cmd");
+    let existed = fs::symlink_metadata(&shim_bash_path).await.is_ok()
+        && fs::symlink_metadata(&shim_cmd_path).await.is_ok();
 
-    if force {
+    if options.force {
         if let Err(err) = fs::remove_file(&shim_bash_path).await {
             if err.kind() != io::ErrorKind::NotFound {
                 return Err(err.into());
@@ -68,38 +216,177 @@ async fn write_shim(dest: &Path, shim: &SpecBin, force: bool) -> Result<()> {
         }
     }
 
-    fs::write(
-        shim_bash_path,
-        format!(
-            r#"#!/bin/bash
-exec moldau exec {shim} -- "$@"
+    let shim = shim.to_string();
+    fs::write(shim_bash_path, bash_contents(moldau, &shim)).await?;
+    fs::write(shim_cmd_path, cmd_contents(moldau, &shim)).await?;
+
+    Ok(!existed)
+}
+
+#[cfg(windows)]
+fn bash_contents(moldau: &Path, shim: &str) -> String {
+    format!(
+        r#"#!/bin/bash
+exec {moldau} exec {shim} -- "$@"
 "#,
-        ),
+        moldau = moldau.display()
     )
-    .await?;
+}
 
-    fs::write(
-        shim_cmd_path,
-        format!(
-            r"@echo off
+#[cfg(windows)]
+fn cmd_contents(moldau: &Path, shim: &str) -> String {
+    format!(
+        r"@echo off
 setlocal
-moldau exec {shim} -- %*
-"
-        ),
+{moldau} exec {shim} -- %*
+",
+        moldau = moldau.display()
     )
-    .await?;
+}
 
-    Ok(())
+/// Copy moldau to the shim's name, which works where neither symlinks nor
+/// wrapper scripts do. Hardlinks are used where possible to save space.
+/// Returns whether the copy did not exist before.
+async fn write_copy(dest: &Path, shim: &SpecBin, options: &ShimsOptions) -> Result<bool> {
+    let current_exe = env::current_exe()?;
+    let current_exe = current_exe.canonicalize().unwrap_or(current_exe);
+
+    let shim_path = dest
+        .join(shim.to_string())
+        .with_extension(env::consts::EXE_EXTENSION);
+    let existed = fs::symlink_metadata(&shim_path).await.is_ok();
+
+    if options.force
+        && let Err(err) = fs::remove_file(&shim_path).await
+        && err.kind() != io::ErrorKind::NotFound
+    {
+        return Err(err.into());
+    }
+
+    if fs::symlink_metadata(&shim_path).await.is_ok() {
+        if is_copy(&shim_path).await {
+            return Ok(false);
+        }
+
+        return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+    }
+
+    // Copying preserves the executable bit
+    if fs::hard_link(&current_exe, &shim_path).await.is_err() {
+        fs::copy(&current_exe, &shim_path).await?;
+    }
+
+    Ok(!existed)
 }
 
-pub async fn shims(dest: &Path, force: bool) -> Result<()> {
+/// Whether `path` is an identical copy of the running moldau executable.
+async fn is_copy(path: &Path) -> bool {
+    let Ok(current_exe) = env::current_exe() else {
+        return false;
+    };
+
+    if !fs::symlink_metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.is_file())
+    {
+        return false;
+    }
+
+    match (fs::read(path).await, fs::read(&current_exe).await) {
+        (Ok(copy), Ok(current)) => copy == current,
+        _ => false,
+    }
+}
+
+/// Whether `path` is exactly what `moldau shims` writes for the bin `name`
+/// into `dest`, so that it can be replaced or removed without touching other
+/// files. moldau itself is never considered a shim.
+#[cfg(unix)]
+async fn is_shim(path: &Path, name: &str, dest: &Path, moldau: &Path) -> bool {
+    if name == "moldau" {
+        return false;
+    }
+
+    if let Ok(target) = fs::read_link(path).await {
+        return target == moldau
+            || dest
+                .canonicalize()
+                .is_ok_and(|dest| target == relative_path(&dest, moldau));
+    }
+
+    fs::read_to_string(path)
+        .await
+        .is_ok_and(|contents| contents == wrapper_contents(moldau, name))
+        || is_copy(path).await
+}
+
+#[cfg(windows)]
+async fn is_shim(path: &Path, name: &str, _dest: &Path, moldau: &Path) -> bool {
+    if name == "moldau" {
+        return false;
+    }
+
+    fs::read_to_string(path).await.is_ok_and(|contents| {
+        contents == bash_contents(moldau, name) || contents == cmd_contents(moldau, name)
+    }) || is_copy(path).await
+}
+
+async fn remove_stale_shims(dest: &Path, moldau: &Path) -> Result<usize> {
+    let mut removed = 0_usize;
+    let mut read_dir = fs::read_dir(dest).await?;
+
+    while let Some(entry) = read_dir.next_entry().await? {
+        let path = entry.path();
+
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+
+        if name.parse::<SpecBin>().is_err() && is_shim(&path, name, dest, moldau).await {
+            fs::remove_file(&path).await?;
+            debug!("removed stale shim {}", path.display());
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+pub async fn shims(dest: &Path, options: &ShimsOptions) -> Result<()> {
     fs::create_dir_all(&dest).await?;
 
+    let moldau = moldau_path(dest)?;
+    let mut added = 0_usize;
+
     for shim in SpecBin::VARIANTS {
-        write_shim(dest, shim, force).await?;
+        let written = if options.copy {
+            write_copy(dest, shim, options).await
+        } else {
+            write_shim(dest, shim, &moldau, options).await
+        }
+        .wrap_err_with(|| {
+            format!(
+                "failed to install {shim} shim; pass `--force` to replace an existing {shim} that is not a moldau shim"
+            )
+        })?;
+
+        if written {
+            added += 1;
+        }
     }
 
-    info!("installed shims into {}", dest.display());
+    if options.sync {
+        let removed = remove_stale_shims(dest, &moldau).await?;
+
+        info!(
+            "synced shims in {} ({} added, {} removed)",
+            dest.display(),
+            added.green(),
+            removed.green()
+        );
+    } else {
+        info!("installed shims into {}", dest.display());
+    }
 
     if !env::var_os("PATH").is_some_and(|s| env::split_paths(&s).any(|p| p == dest)) {
         warn!(
@@ -110,3 +397,149 @@ pub async fn shims(dest: &Path, force: bool) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::fs as std_fs;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn shims_to_uncanonicalized_paths_are_idempotent() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let dest = tmp.path().join("shims");
+        std_fs::create_dir_all(tmp.path().join("bin")).unwrap();
+        std_fs::create_dir(&dest).unwrap();
+        std_fs::write(tmp.path().join("bin/moldau"), "").unwrap();
+
+        let moldau = tmp.path().join("shims/../bin/moldau");
+
+        for options in [
+            ShimsOptions::default(),
+            ShimsOptions {
+                wrapper: true,
+                force: true,
+                ..Default::default()
+            },
+        ] {
+            write_shim(&dest, &SpecBin::Pnpm, &moldau, &options)
+                .await
+                .unwrap();
+            write_shim(
+                &dest,
+                &SpecBin::Pnpm,
+                &moldau,
+                &ShimsOptions {
+                    force: false,
+                    ..options
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(
+            std_fs::read_to_string(dest.join("pnpm")).unwrap(),
+            wrapper_contents(&moldau, "pnpm")
+        );
+    }
+
+    #[test]
+    fn moldau_in_shims_dir_is_detected() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let dest = tmp.path().join("shims");
+        std_fs::create_dir(&dest).unwrap();
+        std_fs::create_dir(tmp.path().join("bin")).unwrap();
+        std::os::unix::fs::symlink(&dest, tmp.path().join("alias")).unwrap();
+
+        assert!(is_in_shims_dir(&dest.join("moldau"), &dest));
+        assert!(is_in_shims_dir(&tmp.path().join("alias/moldau"), &dest));
+        assert!(is_in_shims_dir(
+            &dest.join("moldau"),
+            &tmp.path().join("bin/../shims")
+        ));
+        assert!(!is_in_shims_dir(&tmp.path().join("bin/moldau"), &dest));
+        assert!(!is_in_shims_dir(Path::new("moldau"), &dest));
+    }
+
+    #[test]
+    fn relative_paths_are_computed() {
+        assert_eq!(
+            relative_path(
+                Path::new("/home/user/.local/share/moldau/shims"),
+                Path::new("/home/user/.cargo/bin/moldau")
+            ),
+            Path::new("../../../../.cargo/bin/moldau")
+        );
+        assert_eq!(
+            relative_path(Path::new("/opt/moldau"), Path::new("/opt/moldau/moldau")),
+            Path::new("moldau")
+        );
+        assert_eq!(
+            relative_path(Path::new("/usr/local/bin"), Path::new("/opt/moldau")),
+            Path::new("../../../opt/moldau")
+        );
+    }
+
+    #[tokio::test]
+    async fn relative_shims_resolve_to_moldau() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let dest = tmp.path().join("shims");
+        std_fs::create_dir_all(tmp.path().join("bin")).unwrap();
+        std_fs::create_dir(&dest).unwrap();
+
+        let moldau = tmp.path().canonicalize().unwrap().join("bin/moldau");
+        std_fs::write(&moldau, "").unwrap();
+
+        let options = ShimsOptions {
+            relative: true,
+            ..Default::default()
+        };
+        assert!(
+            write_shim(&dest, &SpecBin::Pnpm, &moldau, &options)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !write_shim(&dest, &SpecBin::Pnpm, &moldau, &options)
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(
+            std_fs::read_link(dest.join("pnpm")).unwrap(),
+            Path::new("../bin/moldau")
+        );
+        assert!(is_shim(&dest.join("pnpm"), "pnpm", &dest, &moldau).await);
+    }
+
+    #[tokio::test]
+    async fn copies_are_idempotent() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let options = ShimsOptions {
+            copy: true,
+            ..Default::default()
+        };
+
+        assert!(
+            write_copy(tmp.path(), &SpecBin::Pnpm, &options)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !write_copy(tmp.path(), &SpecBin::Pnpm, &options)
+                .await
+                .unwrap()
+        );
+        assert!(is_copy(&tmp.path().join("pnpm")).await);
+
+        std_fs::write(tmp.path().join("yarn"), "").unwrap();
+        assert!(
+            write_copy(tmp.path(), &SpecBin::Yarn, &options)
+                .await
+                .is_err()
+        );
+        assert!(!is_copy(&tmp.path().join("yarn")).await);
+    }
+}
@@ -0,0 +1,67 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use eyre::Result;
+use owo_colors::OwoColorize as _;
+use serde::Serialize;
+
+use crate::models::{NpmPackage, Spec, SpecName, SpecVersion};
+
+#[derive(Serialize, Clone, Debug)]
+struct TagEntry {
+    tag: String,
+    version: String,
+    published: bool,
+}
+
+pub async fn tags(name: SpecName, json: bool) -> Result<()> {
+    let package = NpmPackage::fetch(&Spec {
+        name,
+        version: SpecVersion::default(),
+    })
+    .await?;
+
+    let mut entries = package
+        .dist_tags
+        .iter()
+        .map(|(tag, version)| TagEntry {
+            tag: tag.clone(),
+            version: version.clone(),
+            // Tags can point at versions that have since been unpublished
+            published: package.versions.contains_key(version),
+        })
+        .collect::<Vec<_>>();
+
+    entries.sort_unstable_by(|a, b| {
+        (a.tag != "latest")
+            .cmp(&(b.tag != "latest"))
+            .then_with(|| a.tag.cmp(&b.tag))
+    });
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    let width = entries
+        .iter()
+        .map(|entry| entry.tag.len())
+        .max()
+        .unwrap_or(0);
+
+    for entry in &entries {
+        anstream::println!(
+            "{}  {}{}",
+            format!("{:<width$}", entry.tag).blue(),
+            entry.version,
+            if entry.published {
+                String::new()
+            } else {
+                format!("  {}", "(not published)".yellow())
+            }
+        );
+    }
+
+    Ok(())
+}
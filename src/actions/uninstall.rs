@@ -0,0 +1,73 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::PathBuf;
+use tokio::fs;
+
+use eyre::{Result, bail};
+use log::{info, warn};
+use owo_colors::OwoColorize as _;
+
+use crate::{dirs, util};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UninstallOptions {
+    pub yes: bool,
+    pub dry_run: bool,
+}
+
+pub async fn uninstall(options: &UninstallOptions) -> Result<()> {
+    // The data directory contains the default shims directory and config file
+    let mut paths: Vec<PathBuf> = Vec::new();
+
+    for path in [dirs::cache(), dirs::data()] {
+        if path.exists() && !paths.iter().any(|other| path.starts_with(other)) {
+            paths.retain(|other| !other.starts_with(&path));
+            paths.push(path);
+        }
+    }
+
+    warn!(
+        "this does not remove the moldau binary, shims installed outside of the default directory, or `packageManager` pins in projects"
+    );
+
+    if paths.is_empty() {
+        info!("nothing to remove");
+        return Ok(());
+    }
+
+    for path in &paths {
+        info!(
+            "{} {}",
+            if options.dry_run {
+                "would remove"
+            } else {
+                "removing"
+            },
+            path.display().yellow()
+        );
+    }
+
+    if options.dry_run {
+        return Ok(());
+    }
+
+    if !options.yes {
+        if !util::is_interactive() {
+            bail!("pass `--yes` to remove these directories without confirmation");
+        }
+
+        if !util::confirm("remove these directories?")? {
+            bail!("aborted uninstall");
+        }
+    }
+
+    for path in &paths {
+        fs::remove_dir_all(path).await?;
+    }
+
+    info!("removed {} directories", paths.len().green());
+
+    Ok(())
+}
@@ -2,18 +2,19 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::env;
+use std::{env, path::Path};
 use tokio::{fs, io};
 
-use eyre::{Result, eyre};
-use log::info;
+use eyre::{Result, bail, eyre};
+use log::{info, warn};
 use owo_colors::colors::Blue;
 use serde::Serialize;
 
 use crate::{
-    actions::fetch_version,
-    models::{NpmPackage, NpmVersion, Spec, SpecName, SpecVersion, SpecVersionIntegrity},
-    util::LogDisplay as _,
+    actions::spec_integrity,
+    config,
+    models::{NpmPackage, NpmVersion, Spec, SpecVersion, SpecVersionIntegrity},
+    util::{self, LogDisplay as _},
 };
 
 fn detect_indent(s: Option<&str>) -> String {
@@ -45,12 +46,10 @@ fn detect_eol(s: Option<&str>) -> String {
     }
 }
 
-async fn write_package_json(spec: &Spec) -> Result<()> {
+async fn write_package_json(package_json_path: &Path, spec: &Spec) -> Result<()> {
     assert!(spec.version.is_exact());
 
-    let package_json_path = env::current_dir()?.join("package.json");
-
-    let contents = match fs::read_to_string(&package_json_path).await {
+    let contents = match fs::read_to_string(package_json_path).await {
         Ok(contents) => {
             if contents.trim().is_empty() {
                 None
@@ -67,20 +66,42 @@ async fn write_package_json(spec: &Spec) -> Result<()> {
         }
     };
 
+    // The byte order mark is kept as is, so that the file only changes where
+    // the package manager is set
+    let bom = contents
+        .as_deref()
+        .is_some_and(|contents| contents.starts_with(util::UTF8_BOM));
+    let contents = contents.map(|contents| {
+        contents
+            .strip_prefix(util::UTF8_BOM)
+            .map(ToOwned::to_owned)
+            .unwrap_or(contents)
+    });
+
     let (indent, eol) = (
         detect_indent(contents.as_deref()),
         detect_eol(contents.as_deref()),
     );
 
     let mut data = match contents {
-        Some(contents) => serde_json::from_str::<serde_json::Value>(&contents)?,
+        Some(contents) => serde_json::from_str::<serde_json::Value>(&contents).map_err(|err| {
+            eyre!(
+                "package.json at {} is not valid JSON: {err}",
+                package_json_path.display()
+            )
+        })?,
         None => serde_json::json!({}),
     }
     .as_object()
     .ok_or_else(|| eyre!("package.json is not an object"))
     .cloned()?;
 
-    if let Some(inner) = data
+    // Both fields carry the integrity in the build metadata, and are read back
+    // with `packageManager` taking precedence, so it has to be updated whenever
+    // it is present, even if `devEngines.packageManager` is too
+    let has_package_manager = data.contains_key("packageManager");
+
+    let wrote_dev_engines = if let Some(inner) = data
         .get_mut("devEngines")
         .and_then(|v| v.as_object_mut())
         .and_then(|m| m.get_mut("packageManager"))
@@ -88,23 +109,53 @@ async fn write_package_json(spec: &Spec) -> Result<()> {
     {
         inner.insert("name".to_string(), spec.name.to_string().into());
         inner.insert("version".to_string(), spec.version.to_string().into());
+        true
     } else {
+        false
+    };
+
+    if has_package_manager || !wrote_dev_engines {
         data.insert("packageManager".to_string(), spec.to_string().into());
     }
 
     let mut writer = Vec::new();
+    if bom {
+        writer.extend(util::UTF8_BOM.as_bytes());
+    }
+
     data.serialize(&mut serde_json::Serializer::with_formatter(
         &mut writer,
         serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes()),
     ))?;
     writer.extend(eol.as_bytes());
 
-    fs::write(&package_json_path, writer).await?;
+    fs::write(package_json_path, writer).await?;
 
     Ok(())
 }
 
-pub async fn use_(spec: &Spec) -> Result<()> {
+/// Whether `spec` already pins `version` with `integrity`, in which case
+/// package.json is left untouched.
+fn is_pinned_to(
+    spec: &Spec,
+    version: &semver::Version,
+    integrity: Option<&SpecVersionIntegrity>,
+) -> bool {
+    let SpecVersion::Exact(pinned_version) = &spec.version else {
+        return false;
+    };
+
+    pinned_version.cmp_precedence(version).is_eq()
+        && spec.version.integrity().ok().flatten().as_ref() == integrity
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UseOptions {
+    pub allow_downgrade: bool,
+    pub no_integrity: bool,
+}
+
+pub async fn use_(spec: &Spec, options: &UseOptions) -> Result<()> {
     info!(
         "resolving versions that match {}",
         spec.log_display::<Blue>()
@@ -115,7 +166,7 @@ pub async fn use_(spec: &Spec) -> Result<()> {
 
         SpecVersion::SemverReq(req) => NpmPackage::fetch(spec)
             .await?
-            .find_version_req(req)
+            .find_version_req(req, config::get().include_prerelease())
             .ok_or_else(|| eyre!("could not find matching version for {spec}"))?,
 
         SpecVersion::DistTag(tag) => NpmPackage::fetch(spec)
@@ -126,37 +177,80 @@ pub async fn use_(spec: &Spec) -> Result<()> {
 
     let mut version: semver::Version = version_data.version.parse()?;
 
-    if spec.name == SpecName::Yarn {
-        use aws_lc_rs::digest::{SHA512, digest};
+    // An unparseable existing spec cannot be downgraded from, and `use` is a
+    // way to fix it
+    let existing_spec = match Spec::parse(false).await {
+        Ok(existing_spec) => existing_spec,
+        Err(err) => {
+            warn!("replacing unparseable package manager in package.json: {err}");
+            None
+        }
+    }
+    .filter(|existing_spec| existing_spec.name == spec.name);
 
-        // If the package manager is Yarn, we fetch the version and set the integrity
-        // as the hash of the bin file, according to Corepack's special handling (see
-        // `src/actions/fetch.rs` for related details).
+    if !options.allow_downgrade
+        && let Some(existing_spec) = &existing_spec
+        && let SpecVersion::Exact(existing_version) = &existing_spec.version
+        && version.cmp_precedence(existing_version).is_lt()
+    {
+        let message = format!(
+            "{} would be downgraded to {}",
+            existing_spec.log_display::<Blue>(),
+            format!("{}@{version}", spec.name).log_display::<Blue>()
+        );
+
+        if !util::is_interactive() {
+            bail!("{message}; pass `--allow-downgrade` to proceed");
+        }
 
-        let (cache_path, _) = fetch_version(spec, &version_data).await?;
+        if !util::confirm(&format!("{message}, continue?"))? {
+            bail!("aborted downgrade of {existing_spec:#}");
+        }
+    }
 
-        let bin_path = version_data
-            .bin
-            .get("yarn")
-            .ok_or_else(|| eyre!("could not resolve yarn bin path in {version_data}"))?;
+    let existing_integrity = match &existing_spec {
+        Some(existing_spec) => existing_spec.version.integrity()?,
+        None => None,
+    };
 
-        let bin_contents = fs::read(cache_path.join(bin_path)).await?;
+    // Keep the hash algorithm of an existing pin to avoid churn
+    let integrity = if options.no_integrity {
+        None
+    } else {
+        Some(
+            spec_integrity(
+                spec,
+                &version_data,
+                existing_integrity
+                    .as_ref()
+                    .map(SpecVersionIntegrity::algorithm),
+            )
+            .await?,
+        )
+    };
 
-        let sha512 = digest(&SHA512, &bin_contents).as_ref().to_vec();
+    if let Some(existing_spec) = &existing_spec
+        && is_pinned_to(existing_spec, &version, integrity.as_ref())
+    {
+        info!(
+            "package manager is already set to {}",
+            existing_spec.log_display::<Blue>()
+        );
 
-        version.build =
-            semver::BuildMetadata::new(&SpecVersionIntegrity::sha512(sha512).to_string())?;
-    } else {
-        // Otherwise, we set the integrity from data provided by the npm registry.
-        version.build = semver::BuildMetadata::new(&version_data.integrity()?.to_string())?;
+        return Ok(());
     }
 
+    version.build = match &integrity {
+        Some(integrity) => semver::BuildMetadata::new(&integrity.to_string())?,
+        None => semver::BuildMetadata::EMPTY,
+    };
+
     let resolved_spec = Spec {
         name: spec.name,
         version: SpecVersion::Exact(version),
     };
 
-    write_package_json(&resolved_spec).await?;
+    write_package_json(&env::current_dir()?.join("package.json"), &resolved_spec).await?;
     info!(
         "set package manager to {}",
         resolved_spec.log_display::<Blue>()
@@ -164,3 +258,90 @@ pub async fn use_(spec: &Spec) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn matching_pins_are_left_alone() {
+        let integrity = SpecVersionIntegrity::compute(&aws_lc_rs::digest::SHA512, b"moldau");
+        let version = semver::Version::new(9, 4, 0);
+
+        let pinned: Spec = format!("pnpm@9.4.0+{integrity}").parse().unwrap();
+        assert!(is_pinned_to(&pinned, &version, Some(&integrity)));
+        assert!(!is_pinned_to(&pinned, &version, None));
+        assert!(!is_pinned_to(
+            &pinned,
+            &semver::Version::new(9, 5, 0),
+            Some(&integrity)
+        ));
+
+        let unpinned: Spec = "pnpm@9.4.0".parse().unwrap();
+        assert!(is_pinned_to(&unpinned, &version, None));
+        assert!(!is_pinned_to(&unpinned, &version, Some(&integrity)));
+
+        let range: Spec = "pnpm@^9.4.0".parse().unwrap();
+        assert!(!is_pinned_to(&range, &version, None));
+    }
+
+    #[tokio::test]
+    async fn dev_engines_pins_are_read_back() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let path = tmp.path().join("package.json");
+        let integrity = SpecVersionIntegrity::compute(&aws_lc_rs::digest::SHA512, b"moldau");
+        let spec: Spec = format!("pnpm@9.4.0+{integrity}").parse().unwrap();
+
+        fs::write(
+            &path,
+            r#"{ "devEngines": { "packageManager": { "name": "pnpm", "version": "^9.0.0" } } }"#,
+        )
+        .await
+        .unwrap();
+        write_package_json(&path, &spec).await.unwrap();
+
+        let data: serde_json::Value =
+            serde_json::from_slice(&fs::read(&path).await.unwrap()).unwrap();
+        assert!(data.get("packageManager").is_none());
+        assert_eq!(Spec::parse_in(tmp.path(), false).await.unwrap(), Some(spec));
+    }
+
+    #[tokio::test]
+    async fn both_pins_are_updated() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let path = tmp.path().join("package.json");
+        let spec: Spec = "pnpm@9.4.0".parse().unwrap();
+
+        fs::write(
+            &path,
+            r#"{ "packageManager": "pnpm@9.0.0", "devEngines": { "packageManager": { "name": "pnpm", "version": "9.0.0" } } }"#,
+        )
+        .await
+        .unwrap();
+        write_package_json(&path, &spec).await.unwrap();
+
+        let data: serde_json::Value =
+            serde_json::from_slice(&fs::read(&path).await.unwrap()).unwrap();
+        assert_eq!(data["packageManager"], "pnpm@9.4.0");
+        assert_eq!(data["devEngines"]["packageManager"]["version"], "9.4.0");
+    }
+
+    #[tokio::test]
+    async fn byte_order_marks_are_kept() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let path = tmp.path().join("package.json");
+        let spec: Spec = "pnpm@9.4.0".parse().unwrap();
+
+        let contents = format!(
+            "{}{{\n\t\"name\": \"app\",\n\t\"packageManager\": \"pnpm@9.4.0\"\n}}\n",
+            util::UTF8_BOM
+        );
+        fs::write(&path, &contents).await.unwrap();
+        write_package_json(&path, &spec).await.unwrap();
+
+        assert_eq!(fs::read_to_string(&path).await.unwrap(), contents);
+        assert_eq!(Spec::parse_in(tmp.path(), false).await.unwrap(), Some(spec));
+    }
+}
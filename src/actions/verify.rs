@@ -0,0 +1,180 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{fs as std_fs, io, path::Path};
+use tempdir::TempDir;
+use tokio::fs;
+
+use eyre::{Result, bail};
+use log::{info, warn};
+use owo_colors::{OwoColorize as _, colors::Blue};
+
+use crate::{
+    actions::{download_verified, fetch::extract},
+    cache, config,
+    models::{NpmVersion, PackageJsonBinOnly, Spec, SpecName, SpecVersion},
+    platform,
+    util::{self, LogDisplay as _},
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VerifyOptions {
+    pub name: Option<SpecName>,
+    pub deep: bool,
+}
+
+async fn verify_structure(dir: &Path, standalone: bool) -> Result<Vec<String>> {
+    let Ok(package_json) = fs::read(dir.join("package.json")).await else {
+        return Ok(vec!["package.json is missing".to_owned()]);
+    };
+
+    let bin = if standalone {
+        platform::pnpm_standalone_bins()
+    } else {
+        let PackageJsonBinOnly { bin } = serde_json::from_slice(&package_json)?;
+        bin
+    };
+
+    let mut problems = Vec::new();
+
+    for (name, path) in &bin {
+        let bin_path = dir.join(path);
+
+        if !bin_path.is_file() {
+            problems.push(format!("bin {name} ({path}) does not exist"));
+        } else if !util::is_within(dir, &bin_path)? {
+            problems.push(format!("bin {name} ({path}) is outside of the package"));
+        }
+    }
+
+    Ok(problems)
+}
+
+fn compare_trees(expected: &Path, actual: &Path) -> io::Result<Vec<String>> {
+    let expected_files = util::list_files(expected)?;
+    let actual_files = util::list_files(actual)?;
+
+    let mut problems = Vec::new();
+
+    for path in expected_files.union(&actual_files) {
+        let display = path.display();
+
+        match (expected_files.contains(path), actual_files.contains(path)) {
+            (true, false) => problems.push(format!("{display} is missing from the cache")),
+            (false, true) => problems.push(format!("{display} is not in the registry tarball")),
+            _ => {
+                if std_fs::read(expected.join(path))? != std_fs::read(actual.join(path))? {
+                    problems.push(format!("{display} differs from the registry tarball"));
+                }
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+async fn verify_deep(spec: &Spec, dir: &Path, standalone: bool) -> Result<Vec<String>> {
+    let version = if standalone {
+        NpmVersion::fetch_package(
+            &platform::pnpm_standalone_package()?,
+            &format!("{:#}", spec.version),
+        )
+        .await?
+    } else {
+        NpmVersion::fetch(spec).await?
+    };
+    let bytes = download_verified(&version).await?;
+
+    let tmp_dir = config::get().tmp_dir();
+    fs::create_dir_all(&tmp_dir).await?;
+    let unpack_dir = TempDir::new_in(&tmp_dir, "moldau-tmp")?;
+
+    let (root, _) = extract(&version.to_string(), None, &bytes, unpack_dir.path()).await?;
+
+    let cache_dir = dir.to_path_buf();
+    let problems = tokio::task::spawn_blocking(move || compare_trees(&root, &cache_dir)).await??;
+
+    unpack_dir.close()?;
+
+    Ok(problems)
+}
+
+pub async fn verify(options: &VerifyOptions) -> Result<()> {
+    let names = match &options.name {
+        Some(name) => std::slice::from_ref(name),
+        None => SpecName::VARIANTS,
+    };
+
+    let mut verified = 0_usize;
+    let mut failed = 0_usize;
+
+    for name in names {
+        for (versions_dir, standalone) in [
+            (cache::versions_dir(*name), false),
+            (cache::standalone_versions_dir(*name), true),
+        ] {
+            let kind = if standalone { " (standalone)" } else { "" };
+
+            for version in cache::cached_versions_in(&versions_dir).await? {
+                let dir = versions_dir.join(version.to_string());
+                let spec = Spec {
+                    name: *name,
+                    version: SpecVersion::Exact(version),
+                };
+
+                let mut problems = verify_structure(&dir, standalone).await?;
+
+                if options.deep && problems.is_empty() {
+                    problems = verify_deep(&spec, &dir, standalone).await?;
+                }
+
+                verified += 1;
+
+                if problems.is_empty() {
+                    info!("{}{kind} is intact", spec.log_display::<Blue>());
+                } else {
+                    failed += 1;
+
+                    for problem in &problems {
+                        warn!("{}{kind}: {problem}", spec.log_display::<Blue>());
+                    }
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("found problems in {failed} of {verified} cached versions");
+    }
+
+    info!("verified {} cached versions", verified.green());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn standalone_versions_are_checked_for_the_executable() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        std_fs::write(tmp.path().join("package.json"), "{}").unwrap();
+
+        let problems = verify_structure(tmp.path(), true).await.unwrap();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("does not exist"));
+        assert!(
+            verify_structure(tmp.path(), false)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        std_fs::write(tmp.path().join(platform::PNPM_STANDALONE_BIN), "").unwrap();
+        assert!(verify_structure(tmp.path(), true).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::fs;
+
+use eyre::Result;
+use log::debug;
+
+use crate::{config, dirs, models::SpecName};
+
+pub fn versions_dir(name: SpecName) -> PathBuf {
+    dirs::cache().join("versions").join(name.to_string())
+}
+
+pub fn standalone_versions_dir(name: SpecName) -> PathBuf {
+    dirs::cache().join("standalone").join(name.to_string())
+}
+
+pub fn tarballs_dir() -> PathBuf {
+    dirs::cache().join("tarballs")
+}
+
+/// Paths of a retained tarball and its HTTP caching validators.
+pub fn tarball_paths(package: &str, version: &str) -> (PathBuf, PathBuf) {
+    // Scoped package names contain a slash
+    let stem = format!("{}@{version}", package.replace('/', "+"));
+    let dir = tarballs_dir();
+
+    (
+        dir.join(format!("{stem}.tgz")),
+        dir.join(format!("{stem}.json")),
+    )
+}
+
+pub async fn cached_versions(name: SpecName) -> Result<BTreeSet<semver::Version>> {
+    cached_versions_in(&versions_dir(name)).await
+}
+
+pub async fn cached_versions_in(dir: &Path) -> Result<BTreeSet<semver::Version>> {
+    let mut versions = BTreeSet::new();
+
+    if let Ok(mut read_dir) = fs::read_dir(dir).await {
+        while let Some(entry) = read_dir.next_entry().await? {
+            if let Ok(version) = semver::Version::parse(&entry.file_name().to_string_lossy()) {
+                versions.insert(version);
+            }
+        }
+    }
+
+    Ok(versions)
+}
+
+/// Temporary directories older than this are assumed to be left over from an
+/// interrupted fetch rather than belonging to a fetch that is still running.
+const STALE_TEMP_DIR_AGE: Duration = Duration::from_mins(5);
+
+pub fn is_complete(version_dir: &Path) -> bool {
+    version_dir.join("package.json").is_file()
+}
+
+async fn stale_temp_dirs_in(dir: &Path, stale: &mut Vec<PathBuf>) -> Result<()> {
+    let Ok(mut read_dir) = fs::read_dir(dir).await else {
+        return Ok(());
+    };
+
+    while let Some(entry) = read_dir.next_entry().await? {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with("moldau-tmp")
+        {
+            continue;
+        }
+
+        let is_stale = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > STALE_TEMP_DIR_AGE));
+
+        if is_stale {
+            stale.push(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn stale_temp_dirs() -> Result<Vec<PathBuf>> {
+    let mut stale = Vec::new();
+
+    stale_temp_dirs_in(&dirs::cache(), &mut stale).await?;
+    stale_temp_dirs_in(&config::get().tmp_dir(), &mut stale).await?;
+
+    for name in SpecName::VARIANTS {
+        stale_temp_dirs_in(&versions_dir(*name), &mut stale).await?;
+        stale_temp_dirs_in(&standalone_versions_dir(*name), &mut stale).await?;
+    }
+
+    stale.sort_unstable();
+    stale.dedup();
+
+    Ok(stale)
+}
+
+pub async fn remove_stale_temp_dirs() -> Result<usize> {
+    let mut removed = 0_usize;
+
+    for path in stale_temp_dirs().await? {
+        match fs::remove_dir_all(&path).await {
+            Ok(()) => {
+                debug!("removed stale temporary directory {}", path.display());
+                removed += 1;
+            }
+            Err(err) => debug!(
+                "failed to remove stale temporary directory {}: {err}",
+                path.display()
+            ),
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs as std_fs, time::SystemTime};
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn is_complete_requires_package_json() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        assert!(!is_complete(tmp.path()));
+
+        std_fs::write(tmp.path().join("package.json"), "{}").unwrap();
+        assert!(is_complete(tmp.path()));
+    }
+
+    // Directories can only be opened to set their modification time on unix
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn stale_temp_dirs_are_found() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+
+        for name in ["moldau-tmp.stale", "moldau-tmp.fresh", "9.4.0"] {
+            std_fs::create_dir(tmp.path().join(name)).unwrap();
+        }
+
+        let old = SystemTime::now() - STALE_TEMP_DIR_AGE * 2;
+        for name in ["moldau-tmp.stale", "9.4.0"] {
+            std_fs::File::open(tmp.path().join(name))
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+        }
+
+        let mut stale = Vec::new();
+        stale_temp_dirs_in(tmp.path(), &mut stale).await.unwrap();
+
+        assert_eq!(stale, vec![tmp.path().join("moldau-tmp.stale")]);
+    }
+
+    #[tokio::test]
+    async fn missing_dirs_have_no_stale_temp_dirs() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+
+        let mut stale = Vec::new();
+        stale_temp_dirs_in(&tmp.path().join("missing"), &mut stale)
+            .await
+            .unwrap();
+
+        assert!(stale.is_empty());
+    }
+}
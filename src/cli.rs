@@ -0,0 +1,881 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use eyre::{Result, bail};
+use std::{
+    env,
+    io::Write as _,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+use clap::{Args, CommandFactory as _, Parser, Subcommand};
+use owo_colors::OwoColorize as _;
+
+use crate::{
+    actions, config,
+    config::{Config, ProgressFormat},
+    dirs,
+    models::{Spec, SpecBin, SpecName, SpecVersion, SpecVersionIntegrity},
+    util,
+    util::{ExitCodeError, ToExitCode as _},
+};
+
+#[derive(Parser, Clone, Debug)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Args, Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+struct GlobalArgs {
+    /// Path to a config file to use in addition to the default one
+    #[clap(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Format of download progress output (defaults to `MOLDAU_PROGRESS`)
+    #[clap(long, global = true)]
+    progress: Option<ProgressFormat>,
+
+    /// Print newline-delimited JSON progress events to stdout instead of progress bars
+    #[clap(long, global = true)]
+    json_events: bool,
+
+    /// Use pnpm's standalone executable instead of its npm package
+    #[clap(long, global = true)]
+    pnpm_standalone: bool,
+
+    /// Allow version ranges to match pre-release versions
+    #[clap(long, global = true)]
+    include_prerelease: bool,
+
+    /// Resolve version ranges against the registry before using a cached match
+    #[clap(long, global = true)]
+    prefer_online: bool,
+
+    /// Warn when a version range is served from the cache but a newer match exists
+    #[clap(long, global = true)]
+    check_updates: bool,
+
+    /// User agent for registry requests, instead of `moldau/<version>`
+    #[clap(long, global = true)]
+    user_agent: Option<String>,
+
+    /// Registry for package managers in a scope, as `@scope=<registry url>` (repeatable)
+    #[clap(long, global = true, value_name = "SCOPE=URL", value_parser = config::parse_scope_registry)]
+    registry_scope: Vec<(String, String)>,
+
+    /// Timeout in seconds for registry metadata requests (default 30)
+    #[clap(long, global = true, value_name = "SECONDS")]
+    resolve_timeout: Option<u64>,
+
+    /// Timeout in seconds for package downloads (default 600)
+    #[clap(long, global = true, value_name = "SECONDS")]
+    download_timeout: Option<u64>,
+
+    /// Maximum number of package managers to fetch at once when prefetching many (default 4)
+    #[clap(long, global = true, value_name = "N")]
+    max_concurrent_downloads: Option<NonZeroUsize>,
+
+    /// Look for a spec in all parent directories, past repository roots and the home directory
+    #[clap(long, global = true)]
+    no_search_boundary: bool,
+
+    /// Fetch package managers into a temporary directory for `exec` instead of the cache
+    #[clap(long, global = true)]
+    no_cache: bool,
+
+    /// Directory to extract packages in before moving them into the cache
+    #[clap(long, global = true)]
+    tmp_dir: Option<PathBuf>,
+
+    /// Skip verifying registry signatures of downloaded packages (dangerous)
+    #[clap(long, global = true, requires = "i_know_what_im_doing")]
+    no_verify_signature: bool,
+
+    /// Skip verifying integrity hashes of downloaded packages (dangerous)
+    #[clap(long, global = true, requires = "i_know_what_im_doing")]
+    no_verify_integrity: bool,
+
+    /// Confirm the use of options that disable security checks
+    #[clap(long, global = true)]
+    i_know_what_im_doing: bool,
+}
+
+impl From<&GlobalArgs> for Config {
+    fn from(value: &GlobalArgs) -> Self {
+        Self {
+            progress: value.progress,
+            pnpm_standalone: value.pnpm_standalone.then_some(true),
+            include_prerelease: value.include_prerelease.then_some(true),
+            prefer_online: value.prefer_online.then_some(true),
+            check_updates: value.check_updates.then_some(true),
+            user_agent: value.user_agent.clone(),
+            scope_registries: (!value.registry_scope.is_empty())
+                .then(|| value.registry_scope.iter().cloned().collect()),
+            resolve_timeout: value.resolve_timeout,
+            download_timeout: value.download_timeout,
+            max_concurrent_downloads: value.max_concurrent_downloads,
+            search_boundary: value.no_search_boundary.then_some(false),
+            no_cache: value.no_cache.then_some(true),
+            tmp_dir: value.tmp_dir.clone(),
+            no_verify_signature: value.no_verify_signature,
+            no_verify_integrity: value.no_verify_integrity,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum Commands {
+    /// Execute a package manager
+    Exec {
+        /// Specification for the package manager
+        #[clap(long)]
+        spec: Option<Spec>,
+
+        /// Directory to resolve the specification from and run the package manager in
+        #[clap(long)]
+        cwd: Option<PathBuf>,
+
+        /// Node.js runtime to run the package manager with (defaults to `MOLDAU_NODE`)
+        #[clap(long)]
+        node: Option<PathBuf>,
+
+        /// Dotenv file with environment variables to set for the package manager
+        #[clap(long)]
+        env_file: Option<PathBuf>,
+
+        /// Integrity (`<algorithm>.<hex digest>`) that the downloaded tarball must match
+        #[clap(long)]
+        expect_integrity: Option<SpecVersionIntegrity>,
+
+        /// Only run the exact version pinned by the project, never resolving ranges or tags
+        ///
+        /// There is no separate lockfile: the project's `packageManager` or
+        /// `devEngines.packageManager` has to pin an exact version, which is
+        /// taken from the cache or fetched as is.
+        #[clap(long, conflicts_with = "spec")]
+        locked: bool,
+
+        /// Print the path of the binary instead of running it, without any other output
+        ///
+        /// Has to come before the binary, e.g. `moldau exec --print-path pnpm`.
+        #[clap(long)]
+        print_path: bool,
+
+        /// Package manager binary to execute, followed by the arguments to pass to it
+        ///
+        /// Everything after the binary is passed to the package manager as is, so
+        /// `moldau exec pnpm --help` shows pnpm's help.
+        #[clap(
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "BIN"
+        )]
+        command: Vec<String>,
+    },
+
+    /// Use a package manager
+    ///
+    /// Sets to `packageManager` (default) or `devEngines.packageManager` (detected based on usage)
+    Use {
+        /// Specification for the package manager
+        spec: Spec,
+
+        /// Allow replacing the current version with an older one without confirmation
+        #[clap(long)]
+        allow_downgrade: bool,
+
+        /// Write the exact version without the integrity hash
+        #[clap(long)]
+        no_integrity: bool,
+
+        /// Prefetch the specified package manager
+        #[clap(long)]
+        prefetch: bool,
+    },
+
+    /// Upgrade a package manager
+    ///
+    /// Reads from and sets to `packageManager` (default) or `devEngines.packageManager` (detected based on usage)
+    Up {
+        /// Package manager to upgrade to instead of the configured one
+        name: Option<SpecName>,
+
+        /// Version or range to upgrade to instead of the latest version
+        #[clap(long)]
+        to: Option<SpecVersion>,
+
+        /// Allow the upgrade to resolve to an older version without confirmation
+        #[clap(long)]
+        allow_downgrade: bool,
+
+        /// Write the exact version without the integrity hash
+        #[clap(long)]
+        no_integrity: bool,
+
+        /// Prefetch the specified package manager
+        #[clap(long)]
+        prefetch: bool,
+    },
+
+    /// Prefetch a package manager
+    ///
+    /// Reads from `packageManager` or `devEngines.packageManager`, or takes an argument
+    Prefetch {
+        /// Specification for the package manager
+        spec: Option<Spec>,
+
+        /// Fetch again every version that is in the cache, restoring incomplete ones
+        #[clap(long, conflicts_with = "spec")]
+        all_cached: bool,
+
+        /// Read newline-separated specifications from stdin
+        #[clap(long, conflicts_with_all = ["spec", "all_cached"])]
+        stdin: bool,
+
+        /// Only check that the pinned integrity matches the registry
+        #[clap(long, conflicts_with = "all_cached")]
+        check_integrity_only: bool,
+
+        /// Integrity (`<algorithm>.<hex digest>`) that the downloaded tarball must match
+        #[clap(long, conflicts_with_all = ["all_cached", "stdin", "check_integrity_only"])]
+        expect_integrity: Option<SpecVersionIntegrity>,
+
+        /// Number of package managers to fetch at once with `--all-cached` or `--stdin`
+        /// (defaults to `--max-concurrent-downloads`)
+        #[clap(long, value_name = "N")]
+        concurrency: Option<NonZeroUsize>,
+
+        /// Milliseconds to wait between starting fetches with `--all-cached` or `--stdin`
+        #[clap(long, value_name = "MS")]
+        delay: Option<u64>,
+
+        /// Cancel the other fetches with `--all-cached` or `--stdin` as soon as one fails
+        /// integrity or signature verification
+        #[clap(long)]
+        fail_fast: bool,
+
+        /// Fetch the latest `N` versions matching the specification instead of only one
+        #[clap(long, value_name = "N", conflicts_with_all = ["all_cached", "stdin", "check_integrity_only", "expect_integrity"])]
+        last: Option<NonZeroUsize>,
+
+        /// Only resolve the specification and print the version it resolves to, without downloading it
+        #[clap(long, conflicts_with_all = ["all_cached", "stdin", "check_integrity_only", "expect_integrity", "last"])]
+        dry_run: bool,
+    },
+
+    /// Extract a package manager into a directory
+    ///
+    /// Reads from `packageManager` or `devEngines.packageManager`, or takes an argument
+    Extract {
+        /// Specification for the package manager
+        spec: Option<Spec>,
+
+        /// Directory to extract the package manager into
+        #[clap(long, short)]
+        output: PathBuf,
+    },
+
+    /// Print a directory with the package manager's bins, for adding to PATH
+    ///
+    /// Reads from `packageManager` or `devEngines.packageManager`, or takes an argument
+    BinPath {
+        /// Specification for the package manager
+        spec: Option<Spec>,
+
+        /// Fetch the package manager if no matching version is cached, instead of failing
+        #[clap(long)]
+        fetch: bool,
+    },
+
+    /// Show registry metadata for a package manager
+    ///
+    /// Reads from `packageManager` or `devEngines.packageManager`, or takes an argument
+    Info {
+        /// Specification for the package manager
+        spec: Option<Spec>,
+
+        /// Output as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// List the dist tags of a package manager
+    Tags {
+        /// Package manager to list dist tags for
+        name: SpecName,
+
+        /// Output as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// List cached package managers
+    List {
+        /// Output as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Check whether the configured package manager is outdated
+    Outdated {
+        /// Output as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Install shims to a destination directory
+    Shims {
+        /// Directory to write shims into (defaults to `shims` in Moldau's data directory)
+        dest: Option<PathBuf>,
+
+        /// Directory to write shims into, like Corepack's `enable --install-directory`
+        #[clap(long, visible_alias = "install-directory", value_name = "DEST")]
+        install_dir: Option<PathBuf>,
+
+        /// Overwrite shims if destination paths already exist
+        #[clap(short, long)]
+        force: bool,
+
+        /// Update outdated shims and remove shims for binaries that no longer exist
+        #[clap(long)]
+        sync: bool,
+
+        /// Write wrapper scripts instead of symlinks (always the case on Windows)
+        #[clap(long)]
+        wrapper: bool,
+
+        /// Link shims to moldau with relative paths, so that the directory can be moved
+        #[clap(long, visible_alias = "relative-symlinks", conflicts_with = "wrapper")]
+        relative: bool,
+
+        /// Copy the moldau binary to each shim instead, hardlinking it where possible
+        #[clap(long, conflicts_with_all = ["wrapper", "relative"])]
+        copy: bool,
+    },
+
+    /// Clean the package manager cache
+    Clean {
+        /// Only clean versions of this package manager
+        name: Option<SpecName>,
+
+        /// Only clean versions installed from npm packages or standalone executables
+        #[clap(long, value_enum, default_value_t)]
+        scope: actions::CleanScope,
+
+        /// Remove the latest versions of package managers from the cache as well
+        #[clap(short, long)]
+        all: bool,
+
+        /// Number of the latest versions of each package manager to keep (ignored with `--all`)
+        #[clap(long, default_value_t = 1)]
+        keep: usize,
+    },
+
+    /// Check that cached package managers are complete
+    ///
+    /// With `--deep`, every cached version is downloaded again and compared file by file
+    Verify {
+        /// Only verify versions of this package manager
+        name: Option<SpecName>,
+
+        /// Download and extract each version again and compare it against the cache
+        #[clap(long)]
+        deep: bool,
+    },
+
+    /// Diagnose common problems with shims and the cache
+    ///
+    /// Read-only unless `--fix` is passed
+    Doctor {
+        /// Attempt to repair the issues that are found
+        #[clap(long)]
+        fix: bool,
+
+        /// Apply destructive fixes without asking for confirmation
+        #[clap(short, long, requires = "fix")]
+        yes: bool,
+    },
+
+    /// Remove Moldau's cache and data directories, including shims and config
+    ///
+    /// Does not remove the moldau binary itself or `packageManager` pins in projects
+    Uninstall {
+        /// Remove the directories without asking for confirmation
+        #[clap(short, long)]
+        yes: bool,
+
+        /// Only print the directories that would be removed
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Print shell code that adds the shims directory to PATH
+    ///
+    /// For example, add `eval "$(moldau env bash)"` to your `.bashrc`
+    Env {
+        /// The shell to print code for (detected from `SHELL` if omitted)
+        shell: Option<clap_complete::Shell>,
+    },
+
+    /// Export a manifest of cached package managers
+    Export {
+        /// Path to write the manifest to
+        out: PathBuf,
+
+        /// Also write the tarballs next to the manifest for offline restores
+        #[clap(long)]
+        with_tarballs: bool,
+    },
+
+    /// Import package managers from a manifest created by `export`
+    Import {
+        /// Path to the manifest
+        manifest: PathBuf,
+    },
+
+    /// Import package managers from an existing Corepack cache
+    Migrate {
+        /// Corepack home directory to import from (defaults to `COREPACK_HOME`)
+        #[clap(long)]
+        from: Option<PathBuf>,
+    },
+
+    /// Generate shell completions
+    Completions {
+        /// The shell to generate completions for
+        shell: clap_complete::Shell,
+
+        /// Complete specifications and binaries dynamically (Zsh and Fish only)
+        #[clap(long)]
+        dynamic: bool,
+    },
+
+    /// Print completion candidates for dynamic shell completions
+    #[clap(name = "__complete", hide = true)]
+    Complete {
+        /// Kind of candidates to print
+        kind: actions::CompletionKind,
+
+        /// Partial input to filter candidates by
+        #[clap(default_value = "", allow_hyphen_values = true)]
+        partial: String,
+    },
+}
+
+fn init_logger() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("moldau=info"))
+        .format(|buf, record| {
+            let level_style = buf.default_level_style(record.level());
+            writeln!(
+                buf,
+                "{}{}{}{:#}{} {}",
+                "[".dimmed(),
+                level_style,
+                record.level(),
+                level_style,
+                "]".dimmed(),
+                record.args()
+            )
+        })
+        .init();
+}
+
+async fn exec_shim(bin: SpecBin, args: &[String]) -> Result<()> {
+    config::init(config::resolve(Config::default(), None)?);
+
+    let success = actions::exec(bin, args, &actions::ExecOptions::default()).await?;
+
+    if !success {
+        return Err(ExitCodeError::FAILURE.into());
+    }
+
+    Err(ExitCodeError::SUCCESS.into())
+}
+
+/// Determine the shim being invoked from `argv[0]`, if any.
+fn shim_bin(argv0: &str) -> Option<SpecBin> {
+    // `file_stem` strips extensions like `.exe` and `.cmd`
+    let stem = Path::new(argv0).file_stem()?.to_string_lossy();
+
+    // File names are case-insensitive on Windows, so shims can be invoked as
+    // e.g. `PNPM.EXE`
+    if cfg!(windows) {
+        stem.to_lowercase().parse().ok()
+    } else {
+        stem.parse().ok()
+    }
+}
+
+async fn main_fallible() -> Result<()> {
+    init_logger();
+    color_eyre::install()?;
+
+    let mut args = env::args();
+    if let Some(bin) = args.next().and_then(|argv0| shim_bin(&argv0)) {
+        util::set_reporter(util::CliReporter);
+        return exec_shim(bin, &args.collect::<Vec<_>>()).await;
+    }
+
+    let cli = Cli::parse();
+
+    if matches!(
+        cli.command,
+        Commands::Exec {
+            print_path: true,
+            ..
+        }
+    ) {
+        // Only errors may be printed, so that the path can be captured as is
+        log::set_max_level(log::LevelFilter::Error);
+        util::set_reporter(util::SilentReporter);
+    } else if cli.global.json_events {
+        util::set_reporter(util::JsonEventsReporter);
+    } else {
+        util::set_reporter(util::CliReporter);
+    }
+
+    // Completing doesn't need the config, and shouldn't print errors into the
+    // shell if it is broken
+    if !matches!(cli.command, Commands::Complete { .. }) {
+        config::init(config::resolve(
+            Config::from(&cli.global),
+            cli.global.config.as_deref(),
+        )?);
+    }
+
+    run(&cli).await
+}
+
+/// Split the trailing arguments of `exec` into the binary and the arguments
+/// to pass to it.
+fn split_exec_command(command: &[String]) -> (&String, &[String]) {
+    let Some((bin, args)) = command.split_first() else {
+        unreachable!("clap requires at least one value");
+    };
+
+    // An explicit separator is optional, but still accepted
+    let args = args.strip_prefix(&["--".to_owned()]).unwrap_or(args);
+
+    (bin, args)
+}
+
+#[allow(clippy::too_many_lines)]
+async fn run(cli: &Cli) -> Result<()> {
+    match &cli.command {
+        Commands::Exec {
+            spec,
+            cwd,
+            node,
+            env_file,
+            expect_integrity,
+            locked,
+            print_path,
+            command,
+        } => {
+            let (bin, args) = split_exec_command(command);
+
+            let bin: SpecBin = match bin.parse() {
+                Ok(bin) => bin,
+                Err(_) => Cli::command()
+                    .error(
+                        clap::error::ErrorKind::InvalidValue,
+                        format!(
+                            "invalid value '{bin}' for '<BIN>...' (possible values: {})",
+                            SpecBin::VARIANTS
+                                .iter()
+                                .map(ToString::to_string)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                    )
+                    .exit(),
+            };
+
+            let options = actions::ExecOptions {
+                spec: spec.clone(),
+                cwd: cwd.clone(),
+                node: node.clone(),
+                env_file: env_file.clone(),
+                expect_integrity: expect_integrity.clone(),
+                locked: *locked,
+                print_path: *print_path,
+            };
+
+            let success = actions::exec(bin, args, &options).await?;
+            if !success {
+                return Err(ExitCodeError::FAILURE.into());
+            }
+        }
+
+        Commands::Use {
+            spec,
+            allow_downgrade,
+            no_integrity,
+            prefetch,
+        } => {
+            actions::use_(
+                spec,
+                &actions::UseOptions {
+                    allow_downgrade: *allow_downgrade,
+                    no_integrity: *no_integrity,
+                },
+            )
+            .await?;
+
+            if *prefetch {
+                actions::fetch_spec(spec).await?;
+            }
+        }
+
+        Commands::Up {
+            name,
+            to,
+            allow_downgrade,
+            no_integrity,
+            prefetch,
+        } => {
+            let name = match name {
+                Some(name) => *name,
+                None => match Spec::parse(false).await? {
+                    Some(spec) => spec.name,
+                    None => bail!("no `packageManager` or `devEngines.packageManager` configured!"),
+                },
+            };
+
+            let spec = Spec {
+                name,
+                version: to.clone().unwrap_or_default(),
+            };
+
+            actions::use_(
+                &spec,
+                &actions::UseOptions {
+                    allow_downgrade: *allow_downgrade,
+                    no_integrity: *no_integrity,
+                },
+            )
+            .await?;
+
+            if *prefetch {
+                actions::fetch_spec(&spec).await?;
+            }
+        }
+
+        Commands::Prefetch {
+            spec,
+            all_cached,
+            stdin,
+            check_integrity_only,
+            expect_integrity,
+            concurrency,
+            delay,
+            fail_fast,
+            last,
+            dry_run,
+        } => {
+            actions::prefetch(&actions::PrefetchOptions {
+                spec: spec.clone(),
+                all_cached: *all_cached,
+                stdin: *stdin,
+                check_integrity_only: *check_integrity_only,
+                expect_integrity: expect_integrity.clone(),
+                concurrency: *concurrency,
+                delay: delay.map(Duration::from_millis),
+                fail_fast: *fail_fast,
+                last: *last,
+                dry_run: *dry_run,
+            })
+            .await?;
+        }
+
+        Commands::Extract { spec, output } => {
+            actions::extract(spec.as_ref(), output).await?;
+        }
+
+        Commands::BinPath { spec, fetch } => {
+            actions::bin_path(&actions::BinPathOptions {
+                spec: spec.clone(),
+                fetch: *fetch,
+            })
+            .await?;
+        }
+
+        Commands::Info { spec, json } => {
+            actions::info(spec.as_ref(), *json).await?;
+        }
+
+        Commands::Tags { name, json } => {
+            actions::tags(*name, *json).await?;
+        }
+
+        Commands::List { json } => {
+            actions::list(*json).await?;
+        }
+
+        Commands::Outdated { json } => {
+            actions::outdated(*json).await?;
+        }
+
+        Commands::Shims {
+            dest,
+            install_dir,
+            force,
+            sync,
+            wrapper,
+            relative,
+            copy,
+        } => {
+            let dest = match (dest, install_dir) {
+                (Some(dest), Some(install_dir)) if dest != install_dir => Cli::command()
+                    .error(
+                        clap::error::ErrorKind::ArgumentConflict,
+                        "the destination and `--install-dir` must not differ",
+                    )
+                    .exit(),
+                (Some(dest), _) | (None, Some(dest)) => dest.clone(),
+                (None, None) => dirs::shims(),
+            };
+
+            actions::shims(
+                &dest,
+                &actions::ShimsOptions {
+                    force: *force,
+                    sync: *sync,
+                    wrapper: *wrapper,
+                    relative: *relative,
+                    copy: *copy,
+                },
+            )
+            .await?;
+        }
+
+        Commands::Clean {
+            name,
+            scope,
+            all,
+            keep,
+        } => {
+            actions::clean(&actions::CleanOptions {
+                name: *name,
+                scope: *scope,
+                all: *all,
+                keep: *keep,
+            })
+            .await?;
+        }
+
+        Commands::Verify { name, deep } => {
+            actions::verify(&actions::VerifyOptions {
+                name: *name,
+                deep: *deep,
+            })
+            .await?;
+        }
+
+        Commands::Doctor { fix, yes } => {
+            actions::doctor(&actions::DoctorOptions {
+                fix: *fix,
+                yes: *yes,
+            })
+            .await?;
+        }
+
+        Commands::Uninstall { yes, dry_run } => {
+            actions::uninstall(&actions::UninstallOptions {
+                yes: *yes,
+                dry_run: *dry_run,
+            })
+            .await?;
+        }
+
+        Commands::Env { shell } => {
+            actions::env(*shell)?;
+        }
+
+        Commands::Export { out, with_tarballs } => {
+            actions::export(out, *with_tarballs).await?;
+        }
+
+        Commands::Import { manifest } => {
+            actions::import(manifest).await?;
+        }
+
+        Commands::Migrate { from } => {
+            actions::migrate(from.as_deref()).await?;
+        }
+
+        Commands::Completions { shell, dynamic } => {
+            actions::completions(&mut Cli::command(), *shell, *dynamic)?;
+        }
+
+        Commands::Complete { kind, partial } => {
+            actions::complete(*kind, partial).await;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn main() -> ExitCode {
+    main_fallible().await.to_exit_code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shims_are_detected_from_argv0() {
+        assert_eq!(shim_bin("pnpm"), Some(SpecBin::Pnpm));
+        assert_eq!(shim_bin("/usr/local/bin/yarnpkg"), Some(SpecBin::Yarnpkg));
+        assert_eq!(shim_bin("pnpm.exe"), Some(SpecBin::Pnpm));
+        assert_eq!(shim_bin("moldau"), None);
+        assert_eq!(shim_bin("/usr/bin/moldau"), None);
+
+        // Only file names on Windows are case-insensitive
+        assert_eq!(shim_bin("PNPM.EXE").is_some(), cfg!(windows));
+        assert_eq!(shim_bin("Yarnpkg.cmd").is_some(), cfg!(windows));
+    }
+
+    fn exec_command(args: &[&str]) -> Vec<String> {
+        let cli = Cli::try_parse_from(args).unwrap();
+        let Commands::Exec { command, .. } = cli.command else {
+            panic!("expected exec");
+        };
+
+        let (bin, args) = split_exec_command(&command);
+        [bin.clone()].into_iter().chain(args.to_vec()).collect()
+    }
+
+    #[test]
+    fn exec_arguments_are_passed_through() {
+        assert_eq!(
+            exec_command(&["moldau", "exec", "pnpm", "install", "--frozen-lockfile"]),
+            ["pnpm", "install", "--frozen-lockfile"]
+        );
+        assert_eq!(
+            exec_command(&["moldau", "exec", "pnpm", "--", "install"]),
+            ["pnpm", "install"]
+        );
+        assert_eq!(
+            exec_command(&["moldau", "exec", "pnpm", "run", "--", "--watch"]),
+            ["pnpm", "run", "--", "--watch"]
+        );
+        assert_eq!(
+            exec_command(&["moldau", "exec", "pnpm", "--help"]),
+            ["pnpm", "--help"]
+        );
+    }
+}
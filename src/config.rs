@@ -0,0 +1,313 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Display,
+    fs, io,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use eyre::{Result, WrapErr as _, bail, eyre};
+use serde::Deserialize;
+
+use crate::{
+    dirs,
+    models::{Spec, SpecName},
+};
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProgressFormat {
+    /// Animated progress bars
+    #[default]
+    Pretty,
+    /// Periodic log lines, suitable for CI logs
+    Plain,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub progress: Option<ProgressFormat>,
+    pub force_spec: Option<Spec>,
+    pub pnpm_standalone: Option<bool>,
+    pub node: Option<PathBuf>,
+    pub engines_check: Option<bool>,
+    pub include_prerelease: Option<bool>,
+    pub prefer_online: Option<bool>,
+    pub check_updates: Option<bool>,
+    pub user_agent: Option<String>,
+    pub resolve_timeout: Option<u64>,
+    pub download_timeout: Option<u64>,
+    pub max_concurrent_downloads: Option<NonZeroUsize>,
+    pub keep_tarballs: Option<bool>,
+    pub no_cache: Option<bool>,
+    pub tmp_dir: Option<PathBuf>,
+    pub signature_hosts: Option<Vec<String>>,
+    pub fallback_registries: Option<Vec<String>>,
+    pub scope_registries: Option<HashMap<String, String>>,
+    pub search_boundary: Option<bool>,
+
+    // Verification can only be disabled from the command line, so that it is
+    // never turned off for every invocation by accident
+    #[serde(skip)]
+    pub no_verify_signature: bool,
+    #[serde(skip)]
+    pub no_verify_integrity: bool,
+}
+
+fn parse_env_enum<T: ValueEnum>(key: &str) -> Result<Option<T>> {
+    env::var(key)
+        .ok()
+        .map(|value| T::from_str(&value, true).map_err(|err| eyre!("invalid {key}: {err}")))
+        .transpose()
+}
+
+fn parse_env<T: FromStr<Err: Display>>(key: &str) -> Result<Option<T>> {
+    env::var(key)
+        .ok()
+        .map(|value| value.parse().map_err(|err| eyre!("invalid {key}: {err}")))
+        .transpose()
+}
+
+fn parse_env_bool(key: &str) -> Result<Option<bool>> {
+    env::var(key)
+        .ok()
+        .map(|value| match value.to_lowercase().as_str() {
+            "1" | "true" => Ok(true),
+            "0" | "false" => Ok(false),
+            _ => Err(eyre!(
+                "invalid {key}: expected `1`, `0`, `true`, or `false`"
+            )),
+        })
+        .transpose()
+}
+
+fn parse_env_list(key: &str) -> Option<Vec<String>> {
+    env::var(key).ok().map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(ToString::to_string)
+            .collect()
+    })
+}
+
+/// Parse a `@scope=url` mapping of a scope to its registry.
+pub fn parse_scope_registry(s: &str) -> Result<(String, String)> {
+    match s.split_once('=') {
+        Some((scope, registry)) if scope.starts_with('@') && !registry.is_empty() => {
+            Ok((scope.to_string(), registry.to_string()))
+        }
+        _ => bail!("expected `@scope=<registry url>`, got {s:?}"),
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read config file {}", path.display()))?;
+
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("failed to parse config file {}", path.display()))
+    }
+
+    pub fn load_default() -> Result<Self> {
+        let path = dirs::data().join("config.toml");
+
+        match fs::metadata(&path) {
+            Ok(_) => Self::load(&path),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            progress: parse_env_enum("MOLDAU_PROGRESS")?,
+            force_spec: parse_env("MOLDAU_FORCE_SPEC")?,
+            pnpm_standalone: parse_env_bool("MOLDAU_PNPM_STANDALONE")?,
+            node: env::var_os("MOLDAU_NODE").map(PathBuf::from),
+            engines_check: parse_env_bool("MOLDAU_ENGINES_CHECK")?,
+            include_prerelease: parse_env_bool("MOLDAU_INCLUDE_PRERELEASE")?,
+            prefer_online: parse_env_bool("MOLDAU_PREFER_ONLINE")?,
+            check_updates: parse_env_bool("MOLDAU_CHECK_UPDATES")?,
+            user_agent: env::var("MOLDAU_USER_AGENT").ok(),
+            resolve_timeout: parse_env("MOLDAU_RESOLVE_TIMEOUT")?,
+            download_timeout: parse_env("MOLDAU_DOWNLOAD_TIMEOUT")?,
+            max_concurrent_downloads: parse_env("MOLDAU_MAX_CONCURRENT_DOWNLOADS")?,
+            keep_tarballs: parse_env_bool("MOLDAU_KEEP_TARBALLS")?,
+            no_cache: parse_env_bool("MOLDAU_NO_CACHE")?,
+            tmp_dir: env::var_os("MOLDAU_TMP_DIR").map(PathBuf::from),
+            signature_hosts: parse_env_list("MOLDAU_SIGNATURE_HOSTS"),
+            fallback_registries: parse_env_list("MOLDAU_FALLBACK_REGISTRIES"),
+            scope_registries: parse_env_list("MOLDAU_SCOPE_REGISTRIES")
+                .map(|items| {
+                    items
+                        .iter()
+                        .map(|item| parse_scope_registry(item))
+                        .collect::<Result<_>>()
+                })
+                .transpose()
+                .map_err(|err| eyre!("invalid MOLDAU_SCOPE_REGISTRIES: {err}"))?,
+            search_boundary: parse_env_bool("MOLDAU_SEARCH_BOUNDARY")?,
+            ..Default::default()
+        })
+    }
+
+    /// Fill in unset options from `fallback`, preferring the options already set in `self`.
+    #[must_use]
+    pub fn merge(self, fallback: &Self) -> Self {
+        Self {
+            progress: self.progress.or(fallback.progress),
+            force_spec: self.force_spec.or_else(|| fallback.force_spec.clone()),
+            pnpm_standalone: self.pnpm_standalone.or(fallback.pnpm_standalone),
+            node: self.node.or_else(|| fallback.node.clone()),
+            engines_check: self.engines_check.or(fallback.engines_check),
+            include_prerelease: self.include_prerelease.or(fallback.include_prerelease),
+            prefer_online: self.prefer_online.or(fallback.prefer_online),
+            check_updates: self.check_updates.or(fallback.check_updates),
+            user_agent: self.user_agent.or_else(|| fallback.user_agent.clone()),
+            resolve_timeout: self.resolve_timeout.or(fallback.resolve_timeout),
+            download_timeout: self.download_timeout.or(fallback.download_timeout),
+            max_concurrent_downloads: self
+                .max_concurrent_downloads
+                .or(fallback.max_concurrent_downloads),
+            keep_tarballs: self.keep_tarballs.or(fallback.keep_tarballs),
+            no_cache: self.no_cache.or(fallback.no_cache),
+            tmp_dir: self.tmp_dir.or_else(|| fallback.tmp_dir.clone()),
+            signature_hosts: self
+                .signature_hosts
+                .or_else(|| fallback.signature_hosts.clone()),
+            fallback_registries: self
+                .fallback_registries
+                .or_else(|| fallback.fallback_registries.clone()),
+            scope_registries: self
+                .scope_registries
+                .or_else(|| fallback.scope_registries.clone()),
+            search_boundary: self.search_boundary.or(fallback.search_boundary),
+            no_verify_signature: self.no_verify_signature || fallback.no_verify_signature,
+            no_verify_integrity: self.no_verify_integrity || fallback.no_verify_integrity,
+        }
+    }
+
+    #[must_use]
+    pub fn progress(&self) -> ProgressFormat {
+        self.progress.unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn engines_check(&self) -> bool {
+        self.engines_check.unwrap_or(true)
+    }
+
+    #[must_use]
+    pub fn include_prerelease(&self) -> bool {
+        self.include_prerelease.unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn prefer_online(&self) -> bool {
+        self.prefer_online.unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn check_updates(&self) -> bool {
+        self.check_updates.unwrap_or_default()
+    }
+
+    /// Timeout for each registry metadata request.
+    #[must_use]
+    pub fn resolve_timeout(&self) -> Duration {
+        Duration::from_secs(self.resolve_timeout.unwrap_or(30))
+    }
+
+    /// Timeout for each tarball download, including reading the response.
+    #[must_use]
+    pub fn download_timeout(&self) -> Duration {
+        Duration::from_secs(self.download_timeout.unwrap_or(600))
+    }
+
+    /// Maximum number of package managers fetched at once when prefetching many.
+    #[must_use]
+    pub fn max_concurrent_downloads(&self) -> usize {
+        self.max_concurrent_downloads.map_or(4, NonZeroUsize::get)
+    }
+
+    /// Mirrors that serve packages signed with npmjs.org's public keys.
+    #[must_use]
+    pub fn signature_hosts(&self) -> &[String] {
+        self.signature_hosts.as_deref().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn keep_tarballs(&self) -> bool {
+        self.keep_tarballs.unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn no_cache(&self) -> bool {
+        self.no_cache.unwrap_or_default()
+    }
+
+    /// Directory that packages are extracted in before being moved into the cache.
+    pub fn tmp_dir(&self) -> PathBuf {
+        self.tmp_dir.clone().unwrap_or_else(dirs::cache)
+    }
+
+    /// Whether looking for a spec in parent directories stops at a repository
+    /// root or the home directory.
+    #[must_use]
+    pub fn search_boundary(&self) -> bool {
+        self.search_boundary.unwrap_or(true)
+    }
+
+    #[must_use]
+    pub fn use_standalone(&self, name: SpecName) -> bool {
+        name == SpecName::Pnpm && self.pnpm_standalone.unwrap_or_default()
+    }
+}
+
+/// Resolve the configuration from all sources, in order of precedence: command-line
+/// flags, the explicitly passed config file, environment variables, the default
+/// config file, and finally built-in defaults.
+pub fn resolve(cli: Config, config_path: Option<&Path>) -> Result<Config> {
+    let mut config = cli;
+
+    if let Some(path) = config_path {
+        config = config.merge(&Config::load(path)?);
+    }
+
+    let config = config
+        .merge(&Config::from_env()?)
+        .merge(&Config::load_default()?);
+
+    if let Some(user_agent) = &config.user_agent
+        && reqwest::header::HeaderValue::from_str(user_agent).is_err()
+    {
+        bail!("invalid user agent {user_agent:?}");
+    }
+
+    Ok(config)
+}
+
+pub fn init(config: Config) {
+    CONFIG
+        .set(config)
+        .expect("config should only be initialized once");
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
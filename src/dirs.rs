@@ -26,3 +26,8 @@ pub fn data() -> PathBuf {
 pub fn cache() -> PathBuf {
     STRATEGY.cache_dir()
 }
+
+#[must_use]
+pub fn shims() -> PathBuf {
+    data().join("shims")
+}
@@ -6,12 +6,22 @@ use std::sync::LazyLock;
 
 use reqwest::Client;
 
+use crate::config;
+
 static USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
+// Responses are decompressed transparently with the `gzip`, `brotli`, `zstd`,
+// and `deflate` features of reqwest, which also advertise them in
+// `Accept-Encoding`. Packuments are large, so this matters along with the
+// abbreviated install metadata that is requested for all registry metadata.
 pub static HTTP: LazyLock<Client> = LazyLock::new(|| {
     Client::builder()
+        .gzip(true)
+        .brotli(true)
+        .zstd(true)
+        .deflate(true)
         .https_only(true)
-        .user_agent(USER_AGENT)
+        .user_agent(config::get().user_agent.as_deref().unwrap_or(USER_AGENT))
         .build()
         .unwrap()
 });
@@ -0,0 +1,33 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Resolving, fetching, and verifying Node.js package managers, as used by
+//! the `moldau` CLI.
+//!
+//! [`resolve`] looks up the version a [`Spec`] refers to in the registry, and
+//! [`fetch`] downloads, verifies, and caches it, returning its directory and
+//! binaries. Integrity checks are available on [`NpmVersion`] and [`Spec`].
+//! Progress is reported through a [`ProgressReporter`], which can be replaced
+//! with [`set_reporter`].
+
+mod actions;
+mod cache;
+mod cli;
+mod config;
+mod dirs;
+mod http;
+mod models;
+mod platform;
+mod util;
+
+/// Entry point of the `moldau` binary, which is not part of the public API.
+#[doc(hidden)]
+pub use cli::main as run_cli;
+
+pub use actions::{fetch_spec as fetch, resolve};
+pub use models::{
+    NpmRepository, NpmVersion, NpmVersionDist, NpmVersionSignature, Spec, SpecBin, SpecName,
+    SpecVersion, SpecVersionIntegrity,
+};
+pub use util::{DownloadProgress, ProgressEvent, ProgressReporter, StepProgress, set_reporter};
@@ -0,0 +1,23 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::Spec;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CacheManifest {
+    pub entries: Vec<CacheManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CacheManifestEntry {
+    /// Exact spec, with the integrity in the build metadata
+    pub spec: Spec,
+    /// Tarball path, relative to the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tarball: Option<PathBuf>,
+}
@@ -2,10 +2,12 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+mod manifest;
 mod npm;
 mod package;
 mod spec;
 
+pub use manifest::*;
 pub use npm::*;
 pub use package::*;
 pub use spec::*;
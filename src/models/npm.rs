@@ -8,24 +8,151 @@ use base64::prelude::{BASE64_STANDARD, Engine as _};
 use eyre::{Result, bail, eyre};
 use log::debug;
 use reqwest::{
-    Url,
+    StatusCode, Url,
     header::{self, HeaderMap, HeaderValue},
 };
-use serde::Deserialize;
+use serde::{Deserialize, de::DeserializeOwned};
 
-use super::{Spec, SpecVersionIntegrity};
-use crate::http::HTTP;
+use super::{Spec, SpecVersionIntegrity, version_req_matches};
+use crate::{
+    config::{self, Config},
+    http::HTTP,
+    util::VerificationError,
+};
 
 static NPM_REGISTRY: LazyLock<String> = LazyLock::new(|| {
     env::var("COREPACK_NPM_REGISTRY").unwrap_or_else(|_| "https://registry.npmjs.org".to_string())
 });
 
+/// Parse a registry URL, which has to use HTTPS since the HTTP client refuses
+/// plain HTTP requests. There is deliberately no way to allow them.
+fn parse_registry(registry: &str) -> Result<Url> {
+    let url = Url::parse(registry)
+        .map_err(|err| eyre!("invalid npm registry URL {registry:?}: {err}"))?;
+
+    if url.scheme() != "https" {
+        bail!(
+            "npm registry must use HTTPS, but {url} does not; plain HTTP registries are not supported"
+        );
+    }
+
+    Ok(url)
+}
+
+/// The primary registry, from `COREPACK_NPM_REGISTRY`.
+fn registry_url() -> Result<Url> {
+    parse_registry(&NPM_REGISTRY)
+}
+
+/// The registries to fetch metadata of a package from, in the order they are
+/// tried. Scoped packages can be routed to their own registry, like with
+/// npm's `@scope:registry`.
+fn registry_urls(config: &Config, package_name: &str) -> Result<Vec<Url>> {
+    if let Some((scope, _)) = package_name.split_once('/')
+        && let Some(registry) = config
+            .scope_registries
+            .as_ref()
+            .and_then(|registries| registries.get(scope))
+    {
+        return Ok(vec![parse_registry(registry)?]);
+    }
+
+    let mut urls = vec![registry_url()?];
+
+    for registry in config.fallback_registries.as_deref().unwrap_or_default() {
+        urls.push(parse_registry(registry)?);
+    }
+
+    Ok(urls)
+}
+
+/// Whether packages from the registry are signed with npmjs.org's public keys.
+/// A different port on the same host (e.g. a local proxy) doesn't count, but
+/// mirrors can be trusted explicitly with the `signature-hosts` option.
+fn is_signed_registry(url: &Url, trusted_hosts: &[String]) -> bool {
+    if url.host_str() == Some("registry.npmjs.org") && url.port().is_none() {
+        return true;
+    }
+
+    url.host_str()
+        .is_some_and(|host| trusted_hosts.iter().any(|trusted| trusted == host))
+}
+
+/// Build a URL under a registry, keeping its host and port as is and
+/// tolerating a trailing slash in its path.
+fn registry_endpoint(registry: &Url, segments: &[&str]) -> Result<Url> {
+    let mut url = registry.clone();
+    url.path_segments_mut()
+        .map_err(|()| eyre!("failed to construct npm registry URL"))?
+        .pop_if_empty()
+        .extend(segments);
+
+    Ok(url)
+}
+
+/// Fetch metadata from the first registry that serves it, moving on to the next
+/// one on network errors and 404s, and return the registry it came from.
+async fn fetch_metadata<T: DeserializeOwned>(
+    kind: &str,
+    package_name: &str,
+    segments: &[&str],
+    accept: &'static str,
+) -> Result<(T, Url)> {
+    let primary = registry_url()?;
+    let mut last_err = None;
+
+    for registry in registry_urls(config::get(), package_name)? {
+        let url = registry_endpoint(&registry, segments)?;
+
+        // Credentials are meant for the primary registry only
+        let mut headers = if registry.origin() == primary.origin() {
+            npm_auth_headers()?
+        } else {
+            HeaderMap::new()
+        };
+        headers.insert(header::ACCEPT, HeaderValue::from_static(accept));
+
+        debug!("fetching npm {kind}: {url}");
+
+        let response = match HTTP
+            .get(url.clone())
+            .headers(headers)
+            .timeout(config::get().resolve_timeout())
+            .send()
+            .await
+        {
+            Ok(response) => match response.error_for_status() {
+                Ok(response) => response,
+                Err(err) if err.status() == Some(StatusCode::NOT_FOUND) => {
+                    debug!("{url} was not found, trying the next registry");
+                    last_err = Some(err.into());
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            },
+            Err(err) => {
+                debug!("failed to fetch {url}, trying the next registry: {err}");
+                last_err = Some(err.into());
+                continue;
+            }
+        };
+
+        let data = response.json().await?;
+        debug!("npm {kind} served by {registry}");
+
+        return Ok((data, registry));
+    }
+
+    Err(last_err.unwrap_or_else(|| eyre!("no npm registry configured")))
+}
+
 static NPM_INSTALL_HEADER_ACCEPT: &str =
     "application/vnd.npm.install-v1+json; q=1.0, application/json; q=0.8, */*";
 
-fn npm_common_headers() -> Result<HeaderMap> {
+static NPM_FULL_HEADER_ACCEPT: &str = "application/json";
+
+fn npm_auth_headers() -> Result<HeaderMap> {
     let mut headers = HeaderMap::new();
-    headers.insert(header::ACCEPT, NPM_INSTALL_HEADER_ACCEPT.parse()?);
 
     if let Ok(token) = env::var("COREPACK_NPM_TOKEN") {
         let mut header: HeaderValue = format!("Bearer {token}").parse()?;
@@ -47,6 +174,7 @@ fn npm_common_headers() -> Result<HeaderMap> {
 #[derive(Deserialize, Clone, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct NpmPackage {
+    pub name: String,
     pub versions: HashMap<String, NpmVersion>,
     pub dist_tags: HashMap<String, String>,
 }
@@ -58,6 +186,15 @@ pub struct NpmVersion {
     #[serde(default)]
     pub bin: HashMap<String, String>,
     pub dist: NpmVersionDist,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub homepage: Option<String>,
+    #[serde(default)]
+    pub repository: Option<NpmRepository>,
+    /// Registry that served this version's metadata
+    #[serde(skip)]
+    pub registry: Option<Url>,
 }
 
 impl fmt::Display for NpmVersion {
@@ -75,6 +212,42 @@ pub struct NpmVersionDist {
     pub signatures: Vec<NpmVersionSignature>,
 }
 
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum NpmRepository {
+    Shorthand(String),
+    Object { url: String },
+}
+
+impl NpmRepository {
+    /// The repository as a browsable HTTPS URL, if it can be determined.
+    #[must_use]
+    pub fn web_url(&self) -> Option<String> {
+        let url = match self {
+            Self::Shorthand(url) | Self::Object { url } => url.trim(),
+        };
+
+        // `owner/repo` and `github:owner/repo` shorthands
+        if let Some(path) = url
+            .strip_prefix("github:")
+            .or_else(|| (!url.contains(':') && url.split('/').count() == 2).then_some(url))
+        {
+            return Some(format!("https://github.com/{path}"));
+        }
+
+        let url = url.strip_prefix("git+").unwrap_or(url);
+        let url = url.strip_suffix(".git").unwrap_or(url);
+
+        let mut parsed = Url::parse(url).ok()?;
+        if !matches!(parsed.scheme(), "https" | "http") {
+            parsed =
+                Url::parse(&format!("https://{}{}", parsed.host_str()?, parsed.path())).ok()?;
+        }
+
+        Some(parsed.to_string().trim_end_matches('/').to_owned())
+    }
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct NpmVersionSignature {
     pub keyid: String,
@@ -83,34 +256,88 @@ pub struct NpmVersionSignature {
 
 impl NpmPackage {
     pub async fn fetch(spec: &Spec) -> Result<Self> {
-        let mut url = Url::parse(&NPM_REGISTRY)?;
-        url.path_segments_mut()
-            .map_err(|()| eyre!("failed to construct npm registry URL"))?
-            .push(&spec.to_npm_package_name());
+        // Scoped packuments are requested as `@scope/name`, keeping the scope as
+        // its own path segment
+        let package_name = spec.to_npm_package_name();
+        let (mut package, registry): (Self, _) = fetch_metadata(
+            "package",
+            &package_name,
+            &package_name.split('/').collect::<Vec<_>>(),
+            NPM_INSTALL_HEADER_ACCEPT,
+        )
+        .await?;
+
+        package.verify_name(&package_name)?;
+
+        for version in package.versions.values_mut() {
+            version.registry = Some(registry.clone());
+        }
 
-        debug!("fetching npm package: {url}");
+        Ok(package)
+    }
 
-        Ok(HTTP
-            .get(url)
-            .headers(npm_common_headers()?)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?)
+    /// A misconfigured or poisoned mirror could serve a different package.
+    fn verify_name(&self, package_name: &str) -> Result<()> {
+        if self.name != package_name {
+            bail!(VerificationError(format!(
+                "registry returned package {:?} when {package_name:?} was requested",
+                self.name
+            )));
+        }
+
+        if let Some(version) = self.versions.values().find(|v| v.name != package_name) {
+            bail!(VerificationError(format!(
+                "registry returned {version} in the package {package_name:?}"
+            )));
+        }
+
+        Ok(())
     }
 
     #[must_use]
-    pub fn find_version_req(&self, req: &semver::VersionReq) -> Option<NpmVersion> {
+    /// Versions matching `req`, from the lowest to the highest precedence.
+    fn matching_versions(
+        &self,
+        req: &semver::VersionReq,
+        include_prerelease: bool,
+    ) -> Vec<(semver::Version, &NpmVersion)> {
         let mut parsed_versions = self
             .versions
             .iter()
             .filter_map(|(k, v)| semver::Version::parse(k).ok().map(|s| (s, v)))
-            .filter(|(k, _)| req.matches(k))
+            .filter(|(k, _)| version_req_matches(req, k, include_prerelease))
             .collect::<Vec<_>>();
 
         parsed_versions.sort_unstable_by(|a, b| a.0.cmp_precedence(&b.0));
-        parsed_versions.last().map(|a| a.1).cloned()
+        parsed_versions
+    }
+
+    #[must_use]
+    pub fn find_version_req(
+        &self,
+        req: &semver::VersionReq,
+        include_prerelease: bool,
+    ) -> Option<NpmVersion> {
+        self.matching_versions(req, include_prerelease)
+            .last()
+            .map(|a| a.1)
+            .cloned()
+    }
+
+    /// The `n` highest-precedence versions matching `req`, highest first.
+    #[must_use]
+    pub fn find_latest_versions(
+        &self,
+        req: &semver::VersionReq,
+        include_prerelease: bool,
+        n: usize,
+    ) -> Vec<semver::Version> {
+        self.matching_versions(req, include_prerelease)
+            .into_iter()
+            .rev()
+            .take(n)
+            .map(|a| a.0)
+            .collect()
     }
 
     #[must_use]
@@ -123,25 +350,97 @@ impl NpmPackage {
 }
 
 impl NpmVersion {
+    /// Headers for downloading the tarball, which only include credentials if
+    /// the tarball is hosted by the configured registry, like in Corepack.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the configured registry URL or credentials are invalid.
+    pub fn tarball_headers(&self) -> Result<HeaderMap> {
+        let registry = registry_url()?;
+        let tarball = Url::parse(&self.dist.tarball)?;
+
+        if tarball.origin() == registry.origin() {
+            npm_auth_headers()
+        } else {
+            Ok(HeaderMap::new())
+        }
+    }
+
+    /// Fetch the metadata of the exact version or dist tag in a spec.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the registry cannot be reached or does not have the version.
     pub async fn fetch(spec: &Spec) -> Result<Self> {
-        let mut url = Url::parse(&NPM_REGISTRY)?;
-        url.path_segments_mut()
-            .map_err(|()| eyre!("failed to construct npm registry URL"))?
-            .push(&spec.to_npm_package_name())
-            .push(&format!("{:#}", spec.version));
+        Self::fetch_package(&spec.to_npm_package_name(), &format!("{:#}", spec.version)).await
+    }
 
-        debug!("fetching npm version: {url}");
+    /// Fetch the metadata of a version or dist tag of any package.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the registry cannot be reached or does not have the version.
+    pub async fn fetch_package(package_name: &str, version: &str) -> Result<Self> {
+        // Pushed as a single segment, so a scoped name is requested as
+        // `@scope%2Fname/version` with the slash encoded
+        let segments = [package_name, version];
+        let (mut fetched, mut registry): (Self, _) = fetch_metadata(
+            "version",
+            package_name,
+            &segments,
+            NPM_INSTALL_HEADER_ACCEPT,
+        )
+        .await?;
+
+        if fetched.needs_full_metadata(&registry, config::get().signature_hosts()) {
+            debug!("{fetched} has no signatures, fetching its full metadata");
+
+            (fetched, registry) =
+                fetch_metadata("version", package_name, &segments, NPM_FULL_HEADER_ACCEPT).await?;
+        }
 
-        Ok(HTTP
-            .get(url)
-            .headers(npm_common_headers()?)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?)
+        fetched.verify_name(package_name)?;
+
+        fetched.registry = Some(registry);
+        Ok(fetched)
+    }
+
+    /// Registries may serve abbreviated metadata for versions too, which can
+    /// leave out the signatures that are needed for verification.
+    fn needs_full_metadata(&self, registry: &Url, trusted_hosts: &[String]) -> bool {
+        self.dist.signatures.is_empty() && is_signed_registry(registry, trusted_hosts)
     }
 
+    fn verify_name(&self, package_name: &str) -> Result<()> {
+        if self.name != package_name {
+            bail!(VerificationError(format!(
+                "registry returned {self} when {package_name:?} was requested"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Best-effort link to the release notes of this version.
+    #[must_use]
+    pub fn changelog_url(&self) -> Option<String> {
+        if let Some(repository) = self.repository.as_ref().and_then(NpmRepository::web_url) {
+            if repository.starts_with("https://github.com/") {
+                return Some(format!("{repository}/releases"));
+            }
+
+            return Some(repository);
+        }
+
+        self.homepage.clone()
+    }
+
+    /// Integrity of the tarball, falling back to its SHA-1 shasum.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the registry's integrity or shasum is malformed.
     pub fn integrity(&self) -> Result<SpecVersionIntegrity> {
         if let Some(integrity) = &self.dist.integrity {
             let sha512 = BASE64_STANDARD.decode(
@@ -156,26 +455,41 @@ impl NpmVersion {
         }
     }
 
+    /// Verify a downloaded tarball against the registry's integrity.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the tarball does not match, or the integrity is malformed.
     pub fn verify_integrity(&self, bytes: &[u8]) -> Result<()> {
         if let Err((expected, actual)) = self.integrity()?.verify(bytes) {
-            bail!(
+            bail!(VerificationError(format!(
                 "integrity (download) failed to verify for {self} (expected: {expected}, actual: {actual})"
-            );
+            )));
         }
 
         debug!("integrity (download) verified for {self}");
         Ok(())
     }
 
+    /// Verify the registry signatures of the version against npm's public
+    /// keys, if it was served by the npm registry or a trusted mirror.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a signature made with one of npm's keys is invalid.
     pub fn verify_signature(&self) -> Result<()> {
         use aws_lc_rs::signature::{ECDSA_P256_SHA256_ASN1, ParsedPublicKey};
         use base64::prelude::{BASE64_STANDARD, Engine as _};
 
-        if !Url::parse(NPM_REGISTRY.as_str()).is_ok_and(|url| {
-            url.domain()
-                .is_some_and(|domain| domain == "registry.npmjs.org")
-        }) {
-            debug!("skipped ECDSA signature verification for {self} (not `registry.npmjs.org`)");
+        let registry = match &self.registry {
+            Some(registry) => registry.clone(),
+            None => registry_url()?,
+        };
+
+        if !is_signed_registry(&registry, config::get().signature_hosts()) {
+            debug!(
+                "skipped ECDSA signature verification for {self} (not `registry.npmjs.org` or a trusted mirror)"
+            );
             return Ok(());
         }
 
@@ -215,7 +529,9 @@ impl NpmVersion {
                 let p256_signature = BASE64_STANDARD.decode(&signature.sig)?;
 
                 if let Err(err) = p256_public_key.verify_sig(&p256_message, &p256_signature) {
-                    bail!("ECDSA signature failed to verify for {self}: {err}");
+                    bail!(VerificationError(format!(
+                        "ECDSA signature failed to verify for {self}: {err}"
+                    )));
                 } else {
                     debug!(
                         "ECDSA signature verified for {self} (keyid: {})",
@@ -245,3 +561,177 @@ pub static NPM_REGISTRY_PUBLIC_KEYS: [&NpmRegistryPublicKey; 2] = [
         key: "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEY6Ya7W++7aUPzvMTrezH6Ycx3c+HOKYCcNGybJZSCJq/fd7Qa8uuAKtdIkUQtQiEKERhAmE5lMMJhP8OkDOa2g==",
     },
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registries_must_use_https() {
+        let err = parse_registry("http://registry.npmjs.org").unwrap_err();
+        assert!(err.to_string().contains("must use HTTPS"));
+        assert!(
+            err.to_string()
+                .contains("plain HTTP registries are not supported")
+        );
+
+        assert!(parse_registry("registry.npmjs.org").is_err());
+        assert!(parse_registry("https://registry.npmjs.org").is_ok());
+    }
+
+    #[test]
+    fn other_ports_are_not_signed() {
+        let url = |url: &str| Url::parse(url).unwrap();
+
+        assert!(is_signed_registry(&url("https://registry.npmjs.org"), &[]));
+        assert!(is_signed_registry(&url("https://registry.npmjs.org/"), &[]));
+        assert!(!is_signed_registry(
+            &url("https://registry.npmjs.org:4873"),
+            &[]
+        ));
+        assert!(!is_signed_registry(&url("https://[::1]:4873"), &[]));
+    }
+
+    #[test]
+    fn trusted_mirrors_are_signed() {
+        let mirror = Url::parse("https://registry.npmmirror.com").unwrap();
+        let trusted_hosts = ["registry.npmmirror.com".to_owned()];
+
+        assert!(!is_signed_registry(&mirror, &[]));
+        assert!(is_signed_registry(&mirror, &trusted_hosts));
+        assert!(!is_signed_registry(
+            &Url::parse("https://evil.example").unwrap(),
+            &trusted_hosts
+        ));
+    }
+
+    #[test]
+    fn endpoints_keep_the_registry_authority() {
+        let endpoint = |registry: &str, segments: &[&str]| {
+            registry_endpoint(&Url::parse(registry).unwrap(), segments)
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            endpoint("https://[::1]:4873", &["pnpm"]),
+            "https://[::1]:4873/pnpm"
+        );
+        assert_eq!(
+            endpoint("https://registry:4873/", &["pnpm", "9.4.0"]),
+            "https://registry:4873/pnpm/9.4.0"
+        );
+        assert_eq!(
+            endpoint("https://example.com/npm/", &["pnpm"]),
+            "https://example.com/npm/pnpm"
+        );
+    }
+
+    #[test]
+    fn scoped_endpoints_keep_the_scope() {
+        let registry = Url::parse("https://registry.npmjs.org").unwrap();
+        let spec: Spec = "yarn@4.0.0".parse().unwrap();
+        let package_name = spec.to_npm_package_name();
+        assert_eq!(package_name, "@yarnpkg/cli-dist");
+
+        let packument =
+            registry_endpoint(&registry, &package_name.split('/').collect::<Vec<_>>()).unwrap();
+        assert_eq!(
+            packument.as_str(),
+            "https://registry.npmjs.org/@yarnpkg/cli-dist"
+        );
+
+        let version = registry_endpoint(&registry, &[&package_name, "4.0.0"]).unwrap();
+        assert_eq!(
+            version.as_str(),
+            "https://registry.npmjs.org/@yarnpkg%2Fcli-dist/4.0.0"
+        );
+    }
+
+    fn version(name: &str) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "version": "9.4.0",
+            "dist": {
+                "tarball": "https://registry.npmjs.org/pnpm/-/pnpm-9.4.0.tgz",
+                "shasum": "0000000000000000000000000000000000000000",
+            },
+        })
+    }
+
+    #[test]
+    fn mismatched_names_fail_verification() {
+        let package: NpmPackage = serde_json::from_value(serde_json::json!({
+            "name": "pnpm",
+            "versions": { "9.4.0": version("pnpm") },
+            "dist-tags": { "latest": "9.4.0" },
+        }))
+        .unwrap();
+        assert!(package.verify_name("pnpm").is_ok());
+        assert!(
+            package
+                .verify_name("npm")
+                .unwrap_err()
+                .is::<VerificationError>()
+        );
+
+        let package: NpmPackage = serde_json::from_value(serde_json::json!({
+            "name": "pnpm",
+            "versions": { "9.4.0": version("evil") },
+            "dist-tags": {},
+        }))
+        .unwrap();
+        assert!(package.verify_name("pnpm").is_err());
+
+        let version: NpmVersion = serde_json::from_value(version("evil")).unwrap();
+        assert!(version.verify_name("pnpm").is_err());
+    }
+
+    #[test]
+    fn abbreviated_metadata_is_refetched() {
+        let npmjs = Url::parse("https://registry.npmjs.org").unwrap();
+        let mirror = Url::parse("https://registry.npmmirror.com").unwrap();
+
+        let abbreviated: NpmVersion = serde_json::from_value(version("pnpm")).unwrap();
+        assert!(abbreviated.needs_full_metadata(&npmjs, &[]));
+        assert!(!abbreviated.needs_full_metadata(&mirror, &[]));
+
+        let mut full = version("pnpm");
+        full["dist"]["signatures"] = serde_json::json!([
+            { "keyid": "SHA256:DhQ8wR5APBvFHLF/+Tc+AYvPOdTpcIDqOhxsBHRwC7U", "sig": "MEUCIQ" },
+        ]);
+        let full: NpmVersion = serde_json::from_value(full).unwrap();
+        assert_eq!(full.dist.signatures.len(), 1);
+        assert!(!full.needs_full_metadata(&npmjs, &[]));
+    }
+
+    #[test]
+    fn scoped_packages_use_their_registry() {
+        let config = Config {
+            fallback_registries: Some(vec!["https://registry.npmmirror.com".to_owned()]),
+            scope_registries: Some(HashMap::from([(
+                "@yarnpkg".to_owned(),
+                "https://yarn.example.com/npm/".to_owned(),
+            )])),
+            ..Default::default()
+        };
+
+        let urls = |package_name| {
+            registry_urls(&config, package_name)
+                .unwrap()
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(urls("@yarnpkg/cli-dist"), ["https://yarn.example.com/npm/"]);
+        assert_eq!(
+            urls("pnpm"),
+            [
+                registry_url().unwrap().to_string(),
+                "https://registry.npmmirror.com/".to_owned()
+            ]
+        );
+        assert_eq!(urls("@pnpm/exe").len(), 2);
+    }
+}
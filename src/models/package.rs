@@ -5,7 +5,7 @@
 use std::collections::HashMap;
 
 use eyre::{Result, bail};
-use serde::Deserialize;
+use serde::{Deserialize, de::IgnoredAny};
 
 use super::{Spec, SpecVersion};
 
@@ -14,6 +14,8 @@ use super::{Spec, SpecVersion};
 pub struct PackageJson {
     pub package_manager: Option<String>,
     pub dev_engines: Option<DevEngines>,
+    /// Only checked for presence, to detect workspace roots
+    pub workspaces: Option<IgnoredAny>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -70,3 +72,17 @@ pub struct PackageJsonBinOnly {
     #[serde(default)]
     pub bin: HashMap<String, String>,
 }
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PackageJsonEnginesOnly {
+    #[serde(default)]
+    pub engines: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PackageJsonManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub bin: HashMap<String, String>,
+}
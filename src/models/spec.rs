@@ -3,17 +3,23 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use clap::builder::PossibleValue;
-use eyre::{Result, bail, eyre};
+use eyre::{Result, WrapErr as _, bail, eyre};
 use log::debug;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use std::{
+    collections::HashMap,
     env, fmt, iter,
     path::{self, Path},
     str::FromStr,
 };
-use tokio::fs;
+use tokio::{fs, io};
 
-use super::{NpmVersion, PackageJson};
+use super::PackageJson;
+use crate::{
+    config,
+    util::{UTF8_BOM, VerificationError},
+};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Spec {
@@ -38,28 +44,118 @@ impl<'a> Iterator for SpecPathIterator<'a> {
 }
 
 impl Spec {
+    /// Read the spec configured for the current directory, or any of its
+    /// parents if `traverse` is set.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the current directory is inaccessible or a configured spec is
+    /// invalid.
     pub async fn parse(traverse: bool) -> Result<Option<Self>> {
-        let cwd = env::current_dir()?;
+        Self::parse_in(&env::current_dir()?, traverse).await
+    }
+
+    /// Read the spec configured for `cwd`, or any of its parents if `traverse`
+    /// is set.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a configured spec is invalid.
+    pub async fn parse_in(cwd: &Path, traverse: bool) -> Result<Option<Self>> {
+        let boundary = config::get().search_boundary();
+        let home = etcetera::home_dir().ok();
 
         for ancestor in if traverse {
             SpecPathIterator::Traverse(cwd.ancestors())
         } else {
-            SpecPathIterator::NoTraverse(iter::once(cwd.as_ref()))
+            SpecPathIterator::NoTraverse(iter::once(cwd))
         } {
-            if let Some(data) = fs::read(ancestor.join("package.json"))
-                .await
+            // A stray `package.json` in the home directory does not belong to
+            // the projects inside of it
+            if boundary && ancestor != cwd && home.as_deref() == Some(ancestor) {
+                debug!(
+                    "stopped looking for a spec at home directory {}",
+                    ancestor.display()
+                );
+                break;
+            }
+
+            let package_json_path = ancestor.join("package.json");
+            let package_json = fs::read(&package_json_path).await.ok().and_then(|d| {
+                serde_json::from_slice::<PackageJson>(
+                    d.strip_prefix(UTF8_BOM.as_bytes()).unwrap_or(&d),
+                )
+                .inspect_err(|err| {
+                    debug!(
+                        "ignoring unparseable {}: {err}",
+                        package_json_path.display()
+                    );
+                })
                 .ok()
-                .and_then(|d| serde_json::from_slice::<PackageJson>(&d).ok())
+            });
+
+            if let Some(data) = &package_json
                 && let Some(spec) = data.spec()?
             {
                 debug!("parsed spec from {}: {spec}", ancestor.display());
                 return Ok(Some(spec));
             }
+
+            if let Some(spec) = Self::parse_rc(&ancestor.join(".moldaurc")).await? {
+                debug!("parsed spec from {}: {spec}", ancestor.display());
+                return Ok(Some(spec));
+            }
+
+            // Packages in a workspace inherit the spec of the workspace root,
+            // but nothing outside of it
+            if package_json.is_some_and(|data| data.workspaces.is_some())
+                || fs::try_exists(ancestor.join("pnpm-workspace.yaml"))
+                    .await
+                    .unwrap_or_default()
+            {
+                debug!(
+                    "stopped looking for a spec at workspace root {}",
+                    ancestor.display()
+                );
+                break;
+            }
+
+            if boundary
+                && fs::try_exists(ancestor.join(".git"))
+                    .await
+                    .unwrap_or_default()
+            {
+                debug!(
+                    "stopped looking for a spec at repository root {}",
+                    ancestor.display()
+                );
+                break;
+            }
         }
 
         Ok(None)
     }
 
+    async fn parse_rc(path: &Path) -> Result<Option<Self>> {
+        let Ok(contents) = fs::read_to_string(path).await else {
+            return Ok(None);
+        };
+
+        // `.moldaurc` contains a single `name@version` spec, optionally
+        // surrounded by blank lines and `#` comments
+        let Some(line) = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+        else {
+            return Ok(None);
+        };
+
+        line.parse()
+            .map(Some)
+            .wrap_err_with(|| format!("failed to parse spec in {}", path.display()))
+    }
+
     #[must_use]
     pub fn to_npm_package_name(&self) -> String {
         match self.name {
@@ -95,41 +191,78 @@ impl Spec {
         }
     }
 
-    pub async fn verify_integrity(
+    /// Verify the integrity in the spec against the downloaded tarball, before
+    /// it is extracted. Yarn is verified after extraction instead, see
+    /// [`Self::verify_bin_integrity`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the tarball does not match, or the integrity is malformed.
+    pub fn verify_tarball_integrity(&self, bytes: &[u8]) -> Result<()> {
+        if self.name == SpecName::Yarn {
+            return Ok(());
+        }
+
+        if let Some(integrity) = self.version.integrity()?
+            && let Err((expected, actual)) = integrity.verify(bytes)
+        {
+            bail!(VerificationError(format!(
+                "integrity (spec) failed to verify for {self} (expected: {expected}, actual: {actual})"
+            )));
+        }
+
+        debug!("integrity (spec) verified for {self}");
+        Ok(())
+    }
+
+    /// Verify the integrity in a Yarn spec against the extracted bin.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the bin cannot be read or does not match, or the integrity is
+    /// malformed.
+    pub async fn verify_bin_integrity(
         &self,
-        bytes: &[u8],
         unpack_root: &Path,
-        version: &NpmVersion,
+        bins: &HashMap<String, String>,
     ) -> Result<()> {
         // This special handling of integrity verification for Yarn is inherited from
         // Corepack. Corepack downloads Yarn as a file rather than a package, and
         // calculates the hash from that file. We download the package, but calculate
         // the hash for the file anyway for the sake of compatibility.
 
-        if self.name == SpecName::Yarn {
-            if let Some(integrity) = self.version.integrity()? {
-                let bin_path = version
-                    .bin
-                    .get("yarn")
-                    .ok_or_else(|| eyre!("could not resolve yarn bin path in {version}"))?;
+        if self.name != SpecName::Yarn {
+            return Ok(());
+        }
 
-                let bin_contents = fs::read(unpack_root.join(bin_path)).await?;
+        if let Some(integrity) = self.version.integrity()? {
+            let bin_path = bins.get("yarn").ok_or_else(|| {
+                eyre!(
+                    "integrity (spec) could not be verified for {self}: the package does not declare a `yarn` bin"
+                )
+            })?;
 
-                if let Err((expected, actual)) = integrity.verify(&bin_contents) {
+            let bin_contents = match fs::read(unpack_root.join(bin_path)).await {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
                     bail!(
-                        "integrity (spec) failed to verify for {self} (expected: {expected}, actual: {actual})"
+                        "integrity (spec) could not be verified for {self}: bin file {bin_path:?} does not exist in the package"
                     );
                 }
+                Err(err) => {
+                    return Err(err).wrap_err_with(|| {
+                        format!(
+                            "integrity (spec) could not be verified for {self}: failed to read bin file {bin_path:?}"
+                        )
+                    });
+                }
+            };
 
-                debug!("integrity (spec) verified for {self}");
-            }
-        } else {
-            if let Some(integrity) = self.version.integrity()?
-                && let Err((expected, actual)) = integrity.verify(bytes)
-            {
-                bail!(
-                    "integrity (spec) failed to verify for {self} (expected: {expected}, actual: {actual})"
-                );
+            if let Err((expected, actual)) = integrity.verify(&bin_contents) {
+                bail!(VerificationError(format!(
+                    "integrity (spec) failed to verify for {self}: hash of bin file {bin_path:?} ({} bytes) does not match (expected: {expected}, actual: {actual})",
+                    bin_contents.len()
+                )));
             }
 
             debug!("integrity (spec) verified for {self}");
@@ -169,7 +302,7 @@ impl FromStr for Spec {
     }
 }
 
-#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
 pub enum SpecName {
     Npm,
     Yarn,
@@ -208,6 +341,11 @@ impl SpecVersion {
         matches!(self, Self::DistTag(_))
     }
 
+    /// Integrity pinned in the build metadata of an exact version.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the build metadata is not a valid integrity.
     pub fn integrity(&self) -> Result<Option<SpecVersionIntegrity>> {
         match self {
             Self::Exact(v) => SpecVersionIntegrity::parse(&v.build),
@@ -216,6 +354,30 @@ impl SpecVersion {
     }
 }
 
+/// Like [`semver::VersionReq::matches`], which excludes pre-releases unless a
+/// comparator mentions a pre-release of the same version, but optionally lets
+/// pre-releases match as if they were releases.
+#[must_use]
+pub fn version_req_matches(
+    req: &semver::VersionReq,
+    version: &semver::Version,
+    include_prerelease: bool,
+) -> bool {
+    if req.matches(version) {
+        return true;
+    }
+
+    if include_prerelease && !version.pre.is_empty() {
+        let mut release = version.clone();
+        release.pre = semver::Prerelease::EMPTY;
+        release.build = semver::BuildMetadata::EMPTY;
+
+        return req.matches(&release);
+    }
+
+    false
+}
+
 impl fmt::Display for SpecVersion {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(&match self {
@@ -240,14 +402,43 @@ impl FromStr for SpecVersion {
     type Err = eyre::Report;
 
     fn from_str(s: &str) -> Result<Self> {
-        if let Ok(version) = semver::Version::parse(s) {
+        // Accept versions written like Git tags, e.g. `v9.4.0`
+        let version = s
+            .strip_prefix(['v', 'V'])
+            .filter(|rest| rest.starts_with(|ch: char| ch.is_ascii_digit()))
+            .unwrap_or(s);
+
+        if let Ok(version) = semver::Version::parse(version) {
             return Ok(Self::Exact(version));
         }
 
-        if let Ok(version_req) = semver::VersionReq::parse(s) {
+        if let Ok(version_req) = semver::VersionReq::parse(version) {
             return Ok(Self::SemverReq(version_req));
         }
 
+        // Protocols like `workspace:*` and `catalog:default` are meaningful to
+        // package managers for dependencies, but would otherwise be treated as
+        // dist tags here
+        if let Some((protocol, _)) = s.split_once(':')
+            && !protocol.is_empty()
+            && protocol
+                .chars()
+                .all(|ch| ch.is_ascii_lowercase() || ch == '+')
+        {
+            bail!("protocol version {s:?} is not supported for package managers");
+        }
+
+        // Typos in versions, like `9..4`, would otherwise be treated as dist
+        // tags and only fail once they cannot be found in the registry. Tags
+        // like `next-10.x` contain other characters than ranges can.
+        if version.contains(|ch: char| ch.is_ascii_digit())
+            && version
+                .chars()
+                .all(|ch| ch.is_ascii_digit() || "xX*.<>=~^|-, ".contains(ch))
+        {
+            bail!("malformed version {s:?}");
+        }
+
         Ok(Self::DistTag(s.to_owned()))
     }
 }
@@ -265,6 +456,7 @@ pub struct SpecVersionIntegrity {
 }
 
 impl SpecVersionIntegrity {
+    #[must_use]
     pub fn sha1(digest: Vec<u8>) -> Self {
         Self {
             algorithm: &aws_lc_rs::digest::SHA1_FOR_LEGACY_USE_ONLY,
@@ -272,6 +464,7 @@ impl SpecVersionIntegrity {
         }
     }
 
+    #[must_use]
     pub fn sha512(digest: Vec<u8>) -> Self {
         Self {
             algorithm: &aws_lc_rs::digest::SHA512,
@@ -279,6 +472,27 @@ impl SpecVersionIntegrity {
         }
     }
 
+    #[must_use]
+    pub fn compute(algorithm: &'static aws_lc_rs::digest::Algorithm, bytes: &[u8]) -> Self {
+        Self {
+            algorithm,
+            digest: aws_lc_rs::digest::digest(algorithm, bytes)
+                .as_ref()
+                .to_vec(),
+        }
+    }
+
+    #[must_use]
+    pub fn algorithm(&self) -> &'static aws_lc_rs::digest::Algorithm {
+        self.algorithm
+    }
+
+    /// Parse an integrity in `<algorithm>.<hex digest>` form, or `None` if
+    /// the string has no known algorithm prefix.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the digest is not valid hex.
     pub fn parse(s: &str) -> Result<Option<Self>> {
         use aws_lc_rs::digest::{SHA1_FOR_LEGACY_USE_ONLY, SHA224, SHA256, SHA384, SHA512};
 
@@ -312,6 +526,11 @@ impl SpecVersionIntegrity {
         })
     }
 
+    /// Verify bytes against the digest.
+    ///
+    /// # Errors
+    ///
+    /// Returns the expected and actual digests if they do not match.
     pub fn verify(&self, bytes: &[u8]) -> Result<(), (String, String)> {
         use aws_lc_rs::{constant_time::verify_slices_are_equal, digest::digest};
 
@@ -326,6 +545,15 @@ impl SpecVersionIntegrity {
     }
 }
 
+impl FromStr for SpecVersionIntegrity {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)?
+            .ok_or_else(|| eyre!("invalid integrity {s:?}, expected `<algorithm>.<hex digest>`"))
+    }
+}
+
 impl fmt::Display for SpecVersionIntegrity {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use aws_lc_rs::digest::{SHA1_FOR_LEGACY_USE_ONLY, SHA224, SHA256, SHA384, SHA512};
@@ -368,6 +596,7 @@ impl SpecBin {
         Self::Pnpx,
     ];
 
+    #[must_use]
     pub fn to_name(self) -> SpecName {
         match self {
             Self::Npm | Self::Npx => SpecName::Npm,
@@ -426,3 +655,108 @@ impl_fromstr_display! {
     Pnpm = "pnpm",
     Pnpx = "pnpx",
 }
+
+macro_rules! impl_serde_fromstr_display {
+    ($($type:ident),+ $(,)?) => {
+        $(
+            impl Serialize for $type {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $type {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    String::deserialize(deserializer)?
+                        .parse()
+                        .map_err(serde::de::Error::custom)
+                }
+            }
+        )+
+    };
+}
+
+impl_serde_fromstr_display!(Spec, SpecName, SpecVersion, SpecBin);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_versions_are_rejected() {
+        assert!("9..4".parse::<SpecVersion>().is_err());
+        assert!("1.2.3.4".parse::<SpecVersion>().is_err());
+    }
+
+    #[test]
+    fn prefixed_versions_are_exact() {
+        assert_eq!(
+            "v9.4.0".parse::<SpecVersion>().unwrap(),
+            SpecVersion::Exact(semver::Version::new(9, 4, 0))
+        );
+        assert_eq!(
+            "V9".parse::<SpecVersion>().unwrap(),
+            SpecVersion::SemverReq("9".parse().unwrap())
+        );
+        assert_eq!(
+            "9.x".parse::<SpecVersion>().unwrap(),
+            SpecVersion::SemverReq("9.x".parse().unwrap())
+        );
+        assert_eq!(
+            "vnext".parse::<SpecVersion>().unwrap(),
+            SpecVersion::DistTag("vnext".to_owned())
+        );
+    }
+
+    #[test]
+    fn pinned_integrity_keeps_its_algorithm() {
+        let integrity =
+            SpecVersionIntegrity::compute(&aws_lc_rs::digest::SHA224, b"moldau").to_string();
+        assert!(integrity.starts_with("sha224."));
+
+        let spec: Spec = format!("pnpm@9.4.0+{integrity}").parse().unwrap();
+        let pinned = spec.version.integrity().unwrap().unwrap();
+        assert_eq!(pinned.algorithm(), &aws_lc_rs::digest::SHA224);
+        assert_eq!(pinned.to_string(), integrity);
+    }
+
+    #[test]
+    fn prereleases_only_match_when_included() {
+        let beta = semver::Version::parse("9.5.0-beta.1").unwrap();
+        let caret: semver::VersionReq = "^9".parse().unwrap();
+        let prerelease: semver::VersionReq = ">=9.5.0-beta".parse().unwrap();
+
+        assert!(!version_req_matches(&caret, &beta, false));
+        assert!(version_req_matches(&caret, &beta, true));
+        assert!(version_req_matches(&prerelease, &beta, false));
+        assert!(!version_req_matches(
+            &"^10".parse().unwrap(),
+            &semver::Version::parse("10.0.0-rc.1").unwrap(),
+            false
+        ));
+    }
+
+    #[test]
+    fn protocol_versions_are_rejected() {
+        for version in [
+            "workspace:*",
+            "catalog:default",
+            "npm:9.4.0",
+            "git+ssh:repo",
+        ] {
+            assert!(version.parse::<SpecVersion>().is_err(), "{version}");
+        }
+
+        assert!("pnpm@workspace:*".parse::<Spec>().is_err());
+    }
+
+    #[test]
+    fn tags_are_not_malformed_versions() {
+        for tag in ["latest", "next", "canary", "next-10.x", "release-1.0"] {
+            assert_eq!(
+                tag.parse::<SpecVersion>().unwrap(),
+                SpecVersion::DistTag(tag.to_owned())
+            );
+        }
+    }
+}
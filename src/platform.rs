@@ -0,0 +1,61 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{collections::HashMap, env};
+
+use eyre::{Result, bail};
+use log::warn;
+
+pub static PNPM_STANDALONE_BIN: &str = if cfg!(windows) { "pnpm.exe" } else { "pnpm" };
+
+/// Whether the process is an x86_64 binary translated by Rosetta on Apple
+/// Silicon.
+#[cfg(target_os = "macos")]
+pub fn is_translated() -> bool {
+    std::process::Command::new("sysctl")
+        .args(["-n", "sysctl.proc_translated"])
+        .output()
+        .is_ok_and(|output| output.status.success() && output.stdout.trim_ascii() == b"1")
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn is_translated() -> bool {
+    false
+}
+
+pub fn pnpm_standalone_package() -> Result<String> {
+    let os = if cfg!(target_os = "linux") {
+        if cfg!(target_env = "musl") {
+            "linuxstatic"
+        } else {
+            "linux"
+        }
+    } else if cfg!(target_os = "macos") {
+        "macos"
+    } else if cfg!(windows) {
+        "win"
+    } else {
+        bail!("standalone pnpm is not available for {}", env::consts::OS);
+    };
+
+    let arch = match env::consts::ARCH {
+        // Prefer the native executable to the one matching Moldau's architecture
+        "x86_64" if is_translated() => {
+            warn!("Moldau is running under Rosetta, using the native arm64 pnpm executable");
+            "arm64"
+        }
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        arch => bail!("standalone pnpm is not available for {arch}"),
+    };
+
+    Ok(format!("@pnpm/{os}-{arch}"))
+}
+
+pub fn pnpm_standalone_bins() -> HashMap<String, String> {
+    HashMap::from([
+        ("pnpm".to_owned(), PNPM_STANDALONE_BIN.to_owned()),
+        ("pnpx".to_owned(), PNPM_STANDALONE_BIN.to_owned()),
+    ])
+}
@@ -0,0 +1,23 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use eyre::Result;
+use owo_colors::OwoColorize as _;
+use std::io::{self, IsTerminal as _, Write as _};
+
+#[must_use]
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+pub fn confirm(prompt: &str) -> Result<bool> {
+    let mut stderr = anstream::stderr();
+    write!(stderr, "{prompt} {} ", "[y/N]".dimmed())?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "Yes"))
+}
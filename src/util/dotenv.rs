@@ -0,0 +1,69 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use eyre::{Result, bail};
+
+fn parse_value(value: &str) -> Option<String> {
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut parsed = String::new();
+        let mut chars = rest.chars();
+
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' => return Some(parsed),
+                '\\' => match chars.next()? {
+                    'n' => parsed.push('\n'),
+                    't' => parsed.push('\t'),
+                    ch => parsed.push(ch),
+                },
+                ch => parsed.push(ch),
+            }
+        }
+
+        // Unterminated quote
+        None
+    } else if let Some(rest) = value.strip_prefix('\'') {
+        rest.split_once('\'').map(|(value, _)| value.to_owned())
+    } else {
+        let value = value
+            .split_once(" #")
+            .map_or(value, |(value, _)| value)
+            .trim_end();
+
+        Some(value.to_owned())
+    }
+}
+
+/// Parse a dotenv file consisting of `KEY=VALUE` lines. Values can be quoted,
+/// and lines starting with `#` are ignored.
+pub fn parse_dotenv(contents: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {}: expected `KEY=VALUE`", idx + 1);
+        };
+
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            bail!("line {}: invalid key {key:?}", idx + 1);
+        }
+
+        let Some(value) = parse_value(value.trim_start()) else {
+            bail!("line {}: unterminated quoted value", idx + 1);
+        };
+
+        vars.push((key.to_owned(), value));
+    }
+
+    Ok(vars)
+}
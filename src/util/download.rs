@@ -2,44 +2,101 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use eyre::Result;
-use indicatif::{ProgressBar, ProgressStyle};
+use eyre::{Result, bail, eyre};
 use log::debug;
+use reqwest::{
+    StatusCode,
+    header::{self, HeaderMap},
+};
+use serde::{Deserialize, Serialize};
 
-use crate::http::HTTP;
+use super::progress;
+use crate::{config, http::HTTP};
 
-static PROGRESS_CHAR: &str = "━━";
+/// HTTP caching validators of a downloaded resource.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+
+        Self {
+            etag: get(header::ETAG),
+            last_modified: get(header::LAST_MODIFIED),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+pub async fn download(prefix: &str, url: &str, headers: HeaderMap) -> Result<Vec<u8>> {
+    let (bytes, _) = download_if_modified(prefix, url, headers, None)
+        .await?
+        .ok_or_else(|| eyre!("unexpected `304 Not Modified` response from {url}"))?;
 
-pub async fn download(prefix: &str, url: &str) -> Result<Vec<u8>> {
+    Ok(bytes)
+}
+
+/// Download `url` unless it has not been modified since it was downloaded with
+/// `validators`, in which case `None` is returned.
+pub async fn download_if_modified(
+    prefix: &str,
+    url: &str,
+    mut headers: HeaderMap,
+    validators: Option<&CacheValidators>,
+) -> Result<Option<(Vec<u8>, CacheValidators)>> {
     debug!("downloading {url}");
 
-    let mut resp = HTTP.get(url).send().await?.error_for_status()?;
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            headers.insert(header::IF_NONE_MATCH, etag.parse()?);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            headers.insert(header::IF_MODIFIED_SINCE, last_modified.parse()?);
+        }
+    }
+
+    let mut resp = HTTP
+        .get(url)
+        .headers(headers)
+        .timeout(config::get().download_timeout())
+        .send()
+        .await?
+        .error_for_status()?;
+
+    if resp.status() == StatusCode::NOT_MODIFIED {
+        if validators.is_none_or(CacheValidators::is_empty) {
+            bail!("unexpected `304 Not Modified` response from {url}");
+        }
+
+        debug!("{url} has not been modified");
+        return Ok(None);
+    }
+
+    let validators = CacheValidators::from_headers(resp.headers());
     let content_length = resp.content_length().unwrap_or_default();
 
     let mut bytes: Vec<u8> = Vec::with_capacity(content_length.try_into().unwrap_or_default());
 
-    let bar = ProgressBar::new(content_length)
-        .with_prefix(prefix.to_owned())
-        .with_style(
-            ProgressStyle::with_template(
-                r"{prefix:.cyan}  {bar:35.cyan/dim}  {decimal_bytes}/{decimal_total_bytes}  {decimal_bytes_per_sec:.dim}",
-            )?
-            .progress_chars(PROGRESS_CHAR)
-        );
+    let mut progress = progress::reporter().download(prefix, content_length)?;
 
     while let Some(chunk) = resp.chunk().await? {
         bytes.extend_from_slice(&chunk);
-        bar.inc(chunk.len() as u64);
+        progress.advance(chunk.len() as u64);
     }
 
-    bar.set_style(
-        ProgressStyle::with_template(
-            r"{prefix:.green}  {bar:35.green}  {decimal_bytes}/{decimal_total_bytes}  {decimal_bytes_per_sec:.dim}"
-        )?
-        .progress_chars(PROGRESS_CHAR)
-    );
+    progress.finish()?;
 
-    bar.finish();
-
-    Ok(bytes)
+    Ok(Some((bytes, validators)))
 }
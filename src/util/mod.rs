@@ -2,17 +2,34 @@
 //
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+mod confirm;
+mod dotenv;
 mod download;
 mod exit_code_error;
 mod log_display;
+mod progress;
+mod verification_error;
 
-use eyre::Result;
-use std::{borrow::Cow, path::Path};
+use eyre::{Result, eyre};
+use log::debug;
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fs as std_fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use tempdir::TempDir;
 use tokio::fs;
 
+pub use confirm::*;
+pub use dotenv::*;
 pub use download::*;
 pub use exit_code_error::*;
 pub use log_display::*;
+pub use progress::*;
+pub use verification_error::*;
 
 pub async fn find_root(path: &Path) -> Result<Cow<'_, Path>> {
     let mut read_dir = fs::read_dir(&path).await?;
@@ -31,3 +48,211 @@ pub async fn find_root(path: &Path) -> Result<Cow<'_, Path>> {
         None => Ok(Cow::Borrowed(path)),
     }
 }
+
+/// UTF-8 byte order mark, which some editors on Windows write at the start of
+/// `package.json`, but JSON parsers reject.
+pub const UTF8_BOM: &str = "\u{feff}";
+
+/// Format a duration for timing output, e.g. `120ms` or `2.3s`.
+#[must_use]
+pub fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+pub fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+
+    for entry in std_fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+
+    Ok(size)
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut BTreeSet<PathBuf>) -> io::Result<()> {
+    for entry in std_fs::read_dir(dir)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &entry.path(), files)?;
+        } else if let Ok(relative) = entry.path().strip_prefix(root) {
+            files.insert(relative.to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Paths of all files and symlinks below `root`, relative to it.
+pub fn list_files(root: &Path) -> io::Result<BTreeSet<PathBuf>> {
+    let mut files = BTreeSet::new();
+    collect_files(root, root, &mut files)?;
+    Ok(files)
+}
+
+pub fn copy_dir_all(from: &Path, to: &Path) -> io::Result<()> {
+    std_fs::create_dir(to)?;
+
+    for entry in std_fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let dest = to.join(entry.file_name());
+
+        if file_type.is_dir() {
+            copy_dir_all(&entry.path(), &dest)?;
+        } else if file_type.is_symlink() {
+            let target = std_fs::read_link(entry.path())?;
+
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&target, &dest)?;
+            #[cfg(windows)]
+            if entry.path().is_dir() {
+                std::os::windows::fs::symlink_dir(&target, &dest)?;
+            } else {
+                std::os::windows::fs::symlink_file(&target, &dest)?;
+            }
+        } else {
+            std_fs::copy(entry.path(), &dest)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `path` resolves to a location inside `root` after following
+/// symlinks, so that paths from package metadata cannot escape the package.
+pub fn is_within(root: &Path, path: &Path) -> io::Result<bool> {
+    Ok(path.canonicalize()?.starts_with(root.canonicalize()?))
+}
+
+/// Write a file by writing to a temporary file next to it first and renaming it
+/// into place, so that concurrent readers never observe a partially written file.
+pub async fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| eyre!("{} is not a file path", path.display()))?
+        .to_string_lossy();
+
+    let temp_path = path.with_file_name(format!(
+        ".{file_name}.moldau-tmp-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    if let Err(err) = fs::write(&temp_path, contents).await {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(err.into());
+    }
+
+    if let Err(err) = fs::rename(&temp_path, path).await {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+/// Move a directory into place by copying it into a sibling of the
+/// destination first, so that the final move is still a rename on the same
+/// filesystem and other processes never observe a partially copied directory.
+async fn copy_into_place(from: &Path, to: &Path) -> Result<()> {
+    let parent = to
+        .parent()
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+    let staging_dir = TempDir::new_in(&parent, "moldau-tmp")?;
+    let staging_path = staging_dir.path().join("contents");
+
+    let (from_owned, staging_owned) = (from.to_path_buf(), staging_path.clone());
+    tokio::task::spawn_blocking(move || copy_dir_all(&from_owned, &staging_owned)).await??;
+
+    fs::rename(&staging_path, to).await?;
+    staging_dir.close()?;
+    fs::remove_dir_all(from).await?;
+
+    Ok(())
+}
+
+pub async fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    match fs::rename(from, to).await {
+        Ok(()) => Ok(()),
+
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            debug!(
+                "{} and {} are on different filesystems, copying instead of renaming",
+                from.display(),
+                to.display()
+            );
+
+            copy_into_place(from, to).await
+        }
+
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_package(dir: &Path) {
+        std_fs::create_dir_all(dir.join("bin")).unwrap();
+        std_fs::write(dir.join("package.json"), "{}").unwrap();
+        std_fs::write(dir.join("bin").join("pnpm.cjs"), "").unwrap();
+    }
+
+    #[tokio::test]
+    async fn move_dir_renames() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let (from, to) = (tmp.path().join("from"), tmp.path().join("to"));
+        create_package(&from);
+
+        move_dir(&from, &to).await.unwrap();
+
+        assert!(!from.exists());
+        assert!(to.join("package.json").is_file());
+        assert!(to.join("bin").join("pnpm.cjs").is_file());
+    }
+
+    // Renaming across filesystems cannot be simulated, so this exercises the
+    // fallback that `move_dir` uses for `EXDEV` directly
+    #[tokio::test]
+    async fn copy_into_place_moves_contents() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let (from, to) = (tmp.path().join("from"), tmp.path().join("to"));
+        create_package(&from);
+
+        copy_into_place(&from, &to).await.unwrap();
+
+        assert!(!from.exists());
+        assert!(to.join("package.json").is_file());
+        assert!(to.join("bin").join("pnpm.cjs").is_file());
+
+        // The staging directory is cleaned up
+        assert_eq!(std_fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn write_atomic_replaces_files() {
+        let tmp = TempDir::new("moldau-test").unwrap();
+        let path = tmp.path().join("pnpm-9.4.0.tgz");
+
+        write_atomic(&path, "partial").await.unwrap();
+        write_atomic(&path, "complete").await.unwrap();
+
+        assert_eq!(std_fs::read_to_string(&path).unwrap(), "complete");
+        assert_eq!(std_fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,404 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    io::{self, Write as _},
+    path::Path,
+    sync::{LazyLock, OnceLock},
+    time::{Duration, Instant},
+};
+
+use eyre::Result;
+use indicatif::{DecimalBytes, MultiProgress, ProgressBar, ProgressStyle};
+use log::info;
+use serde::Serialize;
+
+use crate::config::{self, ProgressFormat};
+
+/// Receives progress of downloads and other long-running steps, so that
+/// embedders can present it however they like.
+pub trait ProgressReporter: Send + Sync {
+    /// Start reporting a download of `content_length` bytes, which is zero if
+    /// the length is unknown.
+    ///
+    /// # Errors
+    ///
+    /// Failing aborts the download.
+    fn download(
+        &self,
+        prefix: &str,
+        content_length: u64,
+    ) -> Result<Box<dyn DownloadProgress + Send>>;
+
+    /// Start reporting a step without measurable progress, like extracting packages.
+    ///
+    /// # Errors
+    ///
+    /// Failing aborts the step.
+    fn step(&self, prefix: &str, message: &str) -> Result<Box<dyn StepProgress + Send>>;
+
+    /// Receive a milestone of fetching a package manager.
+    fn event(&self, _event: &ProgressEvent<'_>) {}
+}
+
+/// Milestones of fetching a package manager, which are also the stable schema
+/// of `--json-events`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum ProgressEvent<'a> {
+    Resolving {
+        spec: String,
+    },
+    Resolved {
+        spec: String,
+        package: &'a str,
+        version: &'a str,
+    },
+    Downloading {
+        name: &'a str,
+        downloaded: u64,
+        total: Option<u64>,
+        percent: Option<u64>,
+    },
+    Downloaded {
+        name: &'a str,
+        bytes: u64,
+    },
+    Verified {
+        package: &'a str,
+        version: &'a str,
+    },
+    Extracting {
+        name: &'a str,
+    },
+    Done {
+        spec: String,
+        path: &'a Path,
+    },
+}
+
+pub fn emit(event: &ProgressEvent<'_>) {
+    reporter().event(event);
+}
+
+pub trait DownloadProgress {
+    fn advance(&mut self, bytes: u64);
+
+    /// Called when the download completed.
+    ///
+    /// # Errors
+    ///
+    /// Failing fails the download.
+    fn finish(&mut self) -> Result<()>;
+}
+
+pub trait StepProgress {
+    /// Called when the step ends, whether it succeeded or not.
+    fn finish(&mut self);
+}
+
+static REPORTER: OnceLock<Box<dyn ProgressReporter>> = OnceLock::new();
+
+/// Replace the default progress bars with a custom reporter. This has to be
+/// done before anything is downloaded.
+///
+/// # Panics
+///
+/// Panics if a reporter was already set or used.
+pub fn set_reporter(reporter: impl ProgressReporter + 'static) {
+    assert!(
+        REPORTER.set(Box::new(reporter)).is_ok(),
+        "progress reporter should only be set once"
+    );
+}
+
+pub fn reporter() -> &'static dyn ProgressReporter {
+    REPORTER.get_or_init(|| Box::new(CliReporter)).as_ref()
+}
+
+/// Indicator for steps without measurable progress, like extracting packages.
+pub struct Spinner(Box<dyn StepProgress + Send>);
+
+impl Spinner {
+    pub fn start(prefix: &str, message: &str) -> Result<Self> {
+        Ok(Self(reporter().step(prefix, message)?))
+    }
+
+    pub fn finish(self) {
+        drop(self);
+    }
+}
+
+impl Drop for Spinner {
+    // Also finish the step when it fails
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// Progress bars in pretty mode and periodic log lines in plain mode.
+pub struct CliReporter;
+
+impl ProgressReporter for CliReporter {
+    fn download(
+        &self,
+        prefix: &str,
+        content_length: u64,
+    ) -> Result<Box<dyn DownloadProgress + Send>> {
+        Ok(match config::get().progress() {
+            ProgressFormat::Pretty => Box::new(BarProgress::new(prefix, content_length)?),
+            ProgressFormat::Plain => Box::new(PlainProgress::new(prefix, content_length)),
+        })
+    }
+
+    fn step(&self, prefix: &str, message: &str) -> Result<Box<dyn StepProgress + Send>> {
+        match config::get().progress() {
+            ProgressFormat::Pretty => {
+                let spinner = MULTI_PROGRESS
+                    .add(ProgressBar::new_spinner())
+                    .with_prefix(prefix.to_owned())
+                    .with_message(message.to_owned())
+                    .with_style(ProgressStyle::with_template(
+                        r"{prefix:.cyan}  {spinner:.cyan} {msg:.dim}",
+                    )?);
+
+                spinner.enable_steady_tick(Duration::from_millis(100));
+                Ok(Box::new(SpinnerProgress(spinner)))
+            }
+
+            ProgressFormat::Plain => {
+                info!("{prefix}: {message}");
+                Ok(Box::new(NoProgress))
+            }
+        }
+    }
+}
+
+/// Reports nothing, for commands whose output has to stay clean, like
+/// `exec --print-path`.
+pub struct SilentReporter;
+
+impl ProgressReporter for SilentReporter {
+    fn download(
+        &self,
+        _prefix: &str,
+        _content_length: u64,
+    ) -> Result<Box<dyn DownloadProgress + Send>> {
+        Ok(Box::new(NoProgress))
+    }
+
+    fn step(&self, _prefix: &str, _message: &str) -> Result<Box<dyn StepProgress + Send>> {
+        Ok(Box::new(NoProgress))
+    }
+}
+
+/// Newline-delimited JSON events on stdout for `--json-events`, instead of
+/// progress bars.
+pub struct JsonEventsReporter;
+
+impl ProgressReporter for JsonEventsReporter {
+    fn download(
+        &self,
+        prefix: &str,
+        content_length: u64,
+    ) -> Result<Box<dyn DownloadProgress + Send>> {
+        Ok(Box::new(JsonDownloadProgress {
+            name: prefix.to_owned(),
+            total: content_length,
+            downloaded: 0,
+            last_reported: None,
+        }))
+    }
+
+    fn step(&self, _prefix: &str, _message: &str) -> Result<Box<dyn StepProgress + Send>> {
+        Ok(Box::new(NoProgress))
+    }
+
+    fn event(&self, event: &ProgressEvent<'_>) {
+        if let Ok(line) = serde_json::to_string(event) {
+            let _ = writeln!(io::stdout().lock(), "{line}");
+        }
+    }
+}
+
+/// Downloads of unknown size are reported every this many bytes instead of
+/// every percent.
+const JSON_REPORT_BYTES_STEP: u64 = 1_000_000;
+
+struct JsonDownloadProgress {
+    name: String,
+    total: u64,
+    downloaded: u64,
+    last_reported: Option<u64>,
+}
+
+impl JsonDownloadProgress {
+    fn percent(&self) -> Option<u64> {
+        (self.downloaded * 100)
+            .checked_div(self.total)
+            .map(|percent| percent.min(100))
+    }
+
+    fn report(&mut self) {
+        JsonEventsReporter.event(&ProgressEvent::Downloading {
+            name: &self.name,
+            downloaded: self.downloaded,
+            total: (self.total > 0).then_some(self.total),
+            percent: self.percent(),
+        });
+    }
+}
+
+impl DownloadProgress for JsonDownloadProgress {
+    fn advance(&mut self, bytes: u64) {
+        self.downloaded += bytes;
+
+        let mark = self
+            .percent()
+            .unwrap_or(self.downloaded / JSON_REPORT_BYTES_STEP);
+
+        if self.last_reported != Some(mark) {
+            self.last_reported = Some(mark);
+            self.report();
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        JsonEventsReporter.event(&ProgressEvent::Downloaded {
+            name: &self.name,
+            bytes: self.downloaded,
+        });
+
+        Ok(())
+    }
+}
+
+static PROGRESS_CHAR: &str = "━━";
+
+// Concurrent downloads share this so that their progress bars do not overwrite each other
+static MULTI_PROGRESS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+
+const PLAIN_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+const PLAIN_REPORT_PERCENT_STEP: u64 = 10;
+
+struct BarProgress(ProgressBar);
+
+impl BarProgress {
+    fn new(prefix: &str, content_length: u64) -> Result<Self> {
+        Ok(Self(
+            MULTI_PROGRESS.add(ProgressBar::new(content_length))
+                .with_prefix(prefix.to_owned())
+                .with_style(
+                    ProgressStyle::with_template(
+                        r"{prefix:.cyan}  {bar:35.cyan/dim}  {decimal_bytes}/{decimal_total_bytes}  {decimal_bytes_per_sec:.dim}",
+                    )?
+                    .progress_chars(PROGRESS_CHAR)
+                ),
+        ))
+    }
+}
+
+impl DownloadProgress for BarProgress {
+    fn advance(&mut self, bytes: u64) {
+        self.0.inc(bytes);
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.0.set_style(
+            ProgressStyle::with_template(
+                r"{prefix:.green}  {bar:35.green}  {decimal_bytes}/{decimal_total_bytes}  {decimal_bytes_per_sec:.dim}"
+            )?
+            .progress_chars(PROGRESS_CHAR)
+        );
+
+        self.0.finish();
+        Ok(())
+    }
+}
+
+struct PlainProgress {
+    prefix: String,
+    content_length: u64,
+    downloaded: u64,
+    last_report: Instant,
+    last_percent: u64,
+}
+
+impl PlainProgress {
+    fn new(prefix: &str, content_length: u64) -> Self {
+        Self {
+            prefix: prefix.to_owned(),
+            content_length,
+            downloaded: 0,
+            last_report: Instant::now(),
+            last_percent: 0,
+        }
+    }
+
+    fn percent(&self) -> u64 {
+        (self.downloaded * 100)
+            .checked_div(self.content_length)
+            .unwrap_or_default()
+            .min(100)
+    }
+
+    fn report(&mut self) {
+        if self.content_length > 0 {
+            info!(
+                "{}: {}% ({} / {})",
+                self.prefix,
+                self.percent(),
+                DecimalBytes(self.downloaded),
+                DecimalBytes(self.content_length)
+            );
+        } else {
+            info!("{}: {}", self.prefix, DecimalBytes(self.downloaded));
+        }
+
+        self.last_report = Instant::now();
+        self.last_percent = self.percent();
+    }
+}
+
+impl DownloadProgress for PlainProgress {
+    fn advance(&mut self, bytes: u64) {
+        self.downloaded += bytes;
+
+        if self.last_report.elapsed() >= PLAIN_REPORT_INTERVAL
+            || self.percent() >= self.last_percent + PLAIN_REPORT_PERCENT_STEP
+        {
+            self.report();
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.last_percent < 100 {
+            self.report();
+        }
+
+        Ok(())
+    }
+}
+
+struct SpinnerProgress(ProgressBar);
+
+impl StepProgress for SpinnerProgress {
+    fn finish(&mut self) {
+        self.0.finish_and_clear();
+    }
+}
+
+struct NoProgress;
+
+impl StepProgress for NoProgress {
+    fn finish(&mut self) {}
+}
+
+impl DownloadProgress for NoProgress {
+    fn advance(&mut self, _bytes: u64) {}
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
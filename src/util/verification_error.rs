@@ -0,0 +1,29 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{error::Error, fmt};
+
+/// An integrity, signature, or metadata check failed, which can indicate a
+/// compromised registry rather than a transient problem.
+#[derive(Debug)]
+pub struct VerificationError(pub String);
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for VerificationError {}
+
+pub trait IsVerificationError {
+    fn is_verification_error(&self) -> bool;
+}
+
+impl IsVerificationError for eyre::Report {
+    fn is_verification_error(&self) -> bool {
+        self.chain()
+            .any(<dyn Error + 'static>::is::<VerificationError>)
+    }
+}
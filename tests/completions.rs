@@ -0,0 +1,23 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::process::Command;
+
+const MOLDAU: &str = env!("CARGO_BIN_EXE_moldau");
+
+#[test]
+fn dynamic_zsh_completions_use_the_helpers() {
+    let output = Command::new(MOLDAU)
+        .args(["completions", "zsh", "--dynamic"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let script = String::from_utf8(output.stdout).unwrap();
+    assert!(script.starts_with("#compdef moldau\n\n_moldau_dynamic() {"));
+    assert!(script.contains("[Specification for the package manager]:SPEC:_moldau_spec'"));
+    assert!(script.contains(":spec -- Specification for the package manager:_moldau_spec'"));
+    assert!(script.contains(":_moldau_exec_command'"));
+    assert!(!script.contains("Specification for the package manager:_default'"));
+}
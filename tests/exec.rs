@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2025 Ryan Cao <hello@ryanccn.dev>
+//
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::Write as _,
+    os::unix::fs::PermissionsExt as _,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use tempdir::TempDir;
+
+const MOLDAU: &str = env!("CARGO_BIN_EXE_moldau");
+
+/// A project pinning pnpm@9.4.0, with the version already in the cache and a
+/// fake Node.js runtime that runs `node_script` instead of pnpm.
+struct Fixture {
+    tmp: TempDir,
+}
+
+impl Fixture {
+    fn new(node_script: &str) -> Self {
+        let tmp = TempDir::new("moldau-test").unwrap();
+
+        let version_dir = tmp.path().join("cache/moldau/versions/pnpm/9.4.0");
+        fs::create_dir_all(version_dir.join("bin")).unwrap();
+        fs::write(
+            version_dir.join("package.json"),
+            r#"{ "name": "pnpm", "version": "9.4.0", "bin": { "pnpm": "bin/pnpm.cjs" } }"#,
+        )
+        .unwrap();
+        fs::write(version_dir.join("bin/pnpm.cjs"), "").unwrap();
+
+        let project = tmp.path().join("project");
+        fs::create_dir_all(project.join(".git")).unwrap();
+        fs::write(
+            project.join("package.json"),
+            r#"{ "packageManager": "pnpm@9.4.0" }"#,
+        )
+        .unwrap();
+
+        let node = tmp.path().join("node");
+        fs::write(&node, format!("#!/bin/sh\n{node_script}\n")).unwrap();
+        fs::set_permissions(&node, fs::Permissions::from_mode(0o755)).unwrap();
+
+        Self { tmp }
+    }
+
+    fn path(&self) -> &Path {
+        self.tmp.path()
+    }
+
+    fn command(&self, program: impl Into<PathBuf>) -> Command {
+        let mut command = Command::new(program.into());
+        command
+            .current_dir(self.path().join("project"))
+            .env("XDG_CACHE_HOME", self.path().join("cache"))
+            .env("XDG_CONFIG_HOME", self.path().join("config"))
+            .env("MOLDAU_NODE", self.path().join("node"))
+            .env_remove("MOLDAU_EXEC_DEPTH");
+        command
+    }
+}
+
+#[test]
+fn stdin_is_passed_through() {
+    let fixture = Fixture::new("cat");
+
+    let mut child = fixture
+        .command(MOLDAU)
+        .args(["exec", "pnpm"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"from stdin")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"from stdin");
+}
+
+#[test]
+fn copies_dispatch_through_argv0() {
+    let fixture = Fixture::new(r#"shift; printf '%s ' "$@""#);
+
+    let pnpm = fixture.path().join("pnpm");
+    fs::copy(MOLDAU, &pnpm).unwrap();
+
+    let output = fixture
+        .command(&pnpm)
+        .args(["install", "--frozen-lockfile"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"install --frozen-lockfile ");
+}
+
+#[test]
+fn locked_fetches_the_exact_pin_without_resolving() {
+    let fixture = Fixture::new("exit 0");
+    fs::remove_dir_all(fixture.path().join("cache/moldau/versions")).unwrap();
+
+    // Nothing listens on the discard port, so the fetch fails right after
+    // the request that would have been made is logged
+    let output = fixture
+        .command(MOLDAU)
+        .args(["exec", "--locked", "pnpm"])
+        .env("COREPACK_NPM_REGISTRY", "https://127.0.0.1:9")
+        .env("RUST_LOG", "moldau=debug")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("fetching npm version: https://127.0.0.1:9/pnpm/9.4.0"));
+    assert!(!stderr.contains("fetching npm package"));
+}
+
+#[test]
+fn recursive_shims_are_stopped() {
+    // The package manager runs the pnpm shim next to the fake Node.js runtime,
+    // like a shim that resolves to itself through `PATH`
+    let fixture = Fixture::new(r#"exec "$(dirname "$0")/pnpm" "$@""#);
+
+    let pnpm = fixture.path().join("pnpm");
+    fs::copy(MOLDAU, &pnpm).unwrap();
+
+    let output = fixture.command(&pnpm).arg("install").output().unwrap();
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("pnpm was executed recursively 10 times"));
+}
+
+#[test]
+fn doctor_reports_incomplete_standalone_versions() {
+    let fixture = Fixture::new("true");
+    fs::create_dir_all(fixture.path().join("cache/moldau/standalone/pnpm/9.5.0")).unwrap();
+
+    let output = fixture
+        .command(MOLDAU)
+        .arg("doctor")
+        .env("XDG_DATA_HOME", fixture.path().join("data"))
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("cached standalone version pnpm@9.5.0 is incomplete"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("cached version pnpm@9.4.0"), "{stderr}");
+}